@@ -0,0 +1,328 @@
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*,
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::{
+    picking::{PickCamera, PickLayers},
+    world::CurrentLevel,
+    GameSystemSets, Player, PICK_LAYER_CASTERS, TILE_SIZE,
+};
+
+// levels bigger than this scroll instead of being shrunk to fit
+const MAX_VIEW_TILES: f32 = 16.;
+// zoom level 1/4 texel per pixel is as far as the player can zoom out
+const MIN_ZOOM: i32 = -2;
+const MAX_ZOOM: i32 = 8;
+// tiles per second when panning with the arrow keys
+const PAN_SPEED: f32 = 8.;
+
+// Components
+
+#[derive(Component, Default)]
+pub struct GameCamera {
+    // point the camera follows, trails the ship by the dead zone
+    focus: Vec2,
+    // smoothed camera centre before pixel snapping
+    position: Vec2,
+    // false until the camera got moved to the first level
+    placed: bool,
+}
+
+// Resources
+
+#[derive(Resource)]
+pub struct CameraControl {
+    // whole zoom steps on top of the fitted zoom, changed with the mouse wheel
+    pub zoom_steps: i32,
+    // manual offset from the followed ship, in world units
+    pub pan: Vec2,
+    // fraction of the visible half extents the ship can move without the camera following
+    pub dead_zone: Vec2,
+    // higher values catch up with the ship faster
+    pub smoothing: f32,
+}
+
+impl Default for CameraControl {
+    fn default() -> Self {
+        CameraControl {
+            zoom_steps: 0,
+            pan: Vec2::ZERO,
+            dead_zone: Vec2::new(0.3, 0.25),
+            smoothing: 6.,
+        }
+    }
+}
+
+// Plugin
+
+pub struct GameCameraPlugin;
+
+impl Plugin for GameCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraControl>();
+        app.add_startup_system(setup_camera);
+        app.add_system(camera_input.in_set(GameSystemSets::Input));
+        app.add_system(update_camera.after(GameSystemSets::Logic));
+    }
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            transform: Transform::from_xyz(0., 0., 1000.),
+            ..Default::default()
+        },
+        GameCamera::default(),
+        PickCamera {
+            layers: PickLayers::layer(PICK_LAYER_CASTERS),
+        },
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn camera_input(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<&OrthographicProjection, With<GameCamera>>,
+    mut control: ResMut<CameraControl>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    for event in wheel.iter() {
+        if event.y > 0. {
+            control.zoom_steps += 1;
+        } else if event.y < 0. {
+            control.zoom_steps -= 1;
+        }
+    }
+
+    let Ok(projection) = q_camera.get_single() else {
+        return;
+    };
+
+    // drag with the right mouse button, the level sticks to the cursor
+    let cursor = q_window
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+    if mouse.pressed(MouseButton::Right) {
+        if let (Some(last), Some(cursor)) = (*last_cursor, cursor) {
+            control.pan -= (cursor - last) * projection.scale;
+        }
+        *last_cursor = cursor;
+    } else {
+        *last_cursor = None;
+    }
+
+    let mut direction = Vec2::ZERO;
+    if keys.pressed(KeyCode::Left) {
+        direction.x -= 1.;
+    }
+    if keys.pressed(KeyCode::Right) {
+        direction.x += 1.;
+    }
+    if keys.pressed(KeyCode::Down) {
+        direction.y -= 1.;
+    }
+    if keys.pressed(KeyCode::Up) {
+        direction.y += 1.;
+    }
+    control.pan += direction * PAN_SPEED * TILE_SIZE * time.delta_seconds();
+
+    if keys.just_pressed(KeyCode::C) {
+        control.pan = Vec2::ZERO;
+        control.zoom_steps = 0;
+    }
+}
+
+// Zoom levels 1, 2, 3.. are physical pixels per texel, 0, -1.. are 1/2, 1/3.. so
+// texels always cover a whole number of pixels or the other way round.
+fn zoom_level_fitting(fit: f32) -> i32 {
+    if fit >= 1. {
+        fit.floor() as i32
+    } else {
+        2 - (1. / fit).ceil() as i32
+    }
+}
+
+fn pixels_per_texel(zoom_level: i32) -> f32 {
+    if zoom_level >= 1 {
+        zoom_level as f32
+    } else {
+        1. / (2 - zoom_level) as f32
+    }
+}
+
+// keeps the visible area inside the level, or centred on axes where the level is smaller
+fn clamp_to_level(center: Vec2, half_extents: Vec2, level_min: Vec2, level_max: Vec2) -> Vec2 {
+    let mut clamped = center;
+    for axis in 0..2 {
+        if half_extents[axis] * 2. >= level_max[axis] - level_min[axis] {
+            clamped[axis] = (level_min[axis] + level_max[axis]) / 2.;
+        } else {
+            clamped[axis] = center[axis].clamp(
+                level_min[axis] + half_extents[axis],
+                level_max[axis] - half_extents[axis],
+            );
+        }
+    }
+    clamped
+}
+
+fn update_camera(
+    time: Res<Time>,
+    current: Res<CurrentLevel>,
+    mut control: ResMut<CameraControl>,
+    mut resized: EventReader<WindowResized>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_player: Query<&Player>,
+    mut q_camera: Query<(&mut GameCamera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let resized = resized.iter().count() > 0;
+    let Ok(window) = q_window.get_single() else {
+        return;
+    };
+    let rect = current.rect;
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+
+    let level_size = Vec2::new(rect.width as f32, rect.height as f32) * TILE_SIZE;
+    let view_size = level_size.min(Vec2::splat(MAX_VIEW_TILES * TILE_SIZE));
+    let physical_window_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    let fitted = zoom_level_fitting((physical_window_size / view_size).min_element());
+    control.zoom_steps = control
+        .zoom_steps
+        .clamp(MIN_ZOOM - fitted, MAX_ZOOM - fitted);
+    let pixels_per_texel = pixels_per_texel(fitted + control.zoom_steps);
+    let half_extents = physical_window_size / pixels_per_texel / 2.;
+
+    // tile centres sit on multiples of TILE_SIZE, so the level reaches half a tile further out
+    let level_min = Vec2::new(
+        rect.x as f32 * TILE_SIZE - TILE_SIZE / 2.,
+        -((rect.y + rect.height) as f32) * TILE_SIZE + TILE_SIZE / 2.,
+    );
+    let level_max = Vec2::new(
+        (rect.x + rect.width) as f32 * TILE_SIZE - TILE_SIZE / 2.,
+        -(rect.y as f32) * TILE_SIZE + TILE_SIZE / 2.,
+    );
+    // the middle of the fleet
+    let ships = q_player.iter().count();
+    let target = match ships {
+        0 => (level_min + level_max) / 2.,
+        _ => {
+            q_player
+                .iter()
+                .map(|player| Vec2::new(player.x * TILE_SIZE, -player.y * TILE_SIZE))
+                .sum::<Vec2>()
+                / ships as f32
+        }
+    };
+
+    for (mut camera, mut transform, mut projection) in &mut q_camera {
+        let dead_zone = half_extents * control.dead_zone;
+        let offset = target - camera.focus;
+        camera.focus += offset - offset.clamp(-dead_zone, dead_zone);
+        control.pan = control
+            .pan
+            .clamp(level_min - camera.focus, level_max - camera.focus);
+
+        let desired = clamp_to_level(
+            camera.focus + control.pan,
+            half_extents,
+            level_min,
+            level_max,
+        );
+        if !camera.placed || resized {
+            // jump straight to the first level, later level changes glide over
+            camera.placed = true;
+            camera.focus = target;
+            camera.position =
+                clamp_to_level(target + control.pan, half_extents, level_min, level_max);
+        } else {
+            let t = 1. - (-control.smoothing * time.delta_seconds()).exp();
+            camera.position = camera.position.lerp(desired, t);
+        }
+
+        // snap to whole texels so the pixel art does not shimmer while scrolling
+        let snapped = (camera.position * pixels_per_texel).round() / pixels_per_texel;
+        transform.translation.x = snapped.x;
+        transform.translation.y = snapped.y;
+        projection.scale = window.scale_factor() as f32 / pixels_per_texel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitted_zoom_levels_round_down_to_whole_texel_scales() {
+        assert_eq!(zoom_level_fitting(3.5), 3);
+        assert_eq!(zoom_level_fitting(1.), 1);
+        assert_eq!(zoom_level_fitting(0.99), 0);
+        assert_eq!(zoom_level_fitting(0.5), 0);
+        assert_eq!(zoom_level_fitting(0.4), -1);
+        assert_eq!(pixels_per_texel(3), 3.);
+        assert_eq!(pixels_per_texel(0), 0.5);
+        assert_eq!(pixels_per_texel(-1), 1. / 3.);
+        for zoom_level in MIN_ZOOM..=MAX_ZOOM {
+            assert_eq!(zoom_level_fitting(pixels_per_texel(zoom_level)), zoom_level);
+        }
+    }
+
+    #[test]
+    fn the_view_fits_the_window_at_a_whole_texel_scale() {
+        let view_size = Vec2::splat(MAX_VIEW_TILES * TILE_SIZE);
+        let windows = [
+            (3840., 2160.),
+            (1920., 1080.),
+            (1280., 720.),
+            (800., 600.),
+            (200., 150.),
+        ];
+        for (width, height) in windows {
+            let window_size = Vec2::new(width, height);
+            let zoom_level = zoom_level_fitting((window_size / view_size).min_element());
+            let scale = pixels_per_texel(zoom_level);
+            // texels cover whole pixels or the other way round
+            let whole = if scale >= 1. { scale } else { 1. / scale };
+            assert_eq!(whole, whole.round(), "{}x{}", width, height);
+            // the whole view is visible, one zoom level closer it is not
+            assert!((view_size * scale).cmple(window_size).all());
+            let closer = pixels_per_texel(zoom_level + 1);
+            assert!(!(view_size * closer).cmple(window_size).all());
+        }
+    }
+
+    #[test]
+    fn the_view_stops_at_the_level_edges() {
+        let (min, max) = (Vec2::new(0., -50.), Vec2::new(100., 0.));
+        let half_extents = Vec2::new(20., 10.);
+        let clamp = |center| clamp_to_level(center, half_extents, min, max);
+        assert_eq!(clamp(Vec2::new(50., -25.)), Vec2::new(50., -25.));
+        assert_eq!(clamp(Vec2::new(5., -45.)), Vec2::new(20., -40.));
+        assert_eq!(clamp(Vec2::new(-30., 20.)), Vec2::new(20., -10.));
+        assert_eq!(clamp(Vec2::new(95., -48.)), Vec2::new(80., -40.));
+    }
+
+    #[test]
+    fn levels_smaller_than_the_view_are_centred() {
+        let (min, max) = (Vec2::new(0., -50.), Vec2::new(100., 0.));
+        // wider than the level, the level still scrolls up and down
+        let clamped = clamp_to_level(Vec2::new(90., -5.), Vec2::new(60., 10.), min, max);
+        assert_eq!(clamped, Vec2::new(50., -10.));
+        // exactly as big as the level or bigger on both axes
+        for half_extents in [Vec2::new(50., 25.), Vec2::new(80., 40.)] {
+            let clamped = clamp_to_level(Vec2::new(0., 0.), half_extents, min, max);
+            assert_eq!(clamped, Vec2::new(50., -25.));
+        }
+    }
+}
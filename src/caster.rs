@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    caster_value,
+    level::{EntityInstance, Level, LevelRect},
+    loading::GameAssets,
+    move_player, ray_color, retrace_ray, seconds_to_ticks, spawn_ray_sprites, Dir, GameState,
+    GameSystemSets, LevelGrid, Ray, RayCaster, SimulationSets, TILE_SIZE,
+};
+
+// how quickly caster sprites catch up with their tile, per second
+const SLIDE_SPEED: f32 = 12.;
+
+// How a caster moves, authored as a Caster_motion entity on its tile. The
+// pose only depends on the tick and the clicks, so replays see the same, and
+// it starts over whenever a level is entered. Rails have to run over empty
+// tiles, the tile a caster leaves is empty afterwards.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct CasterMotion {
+    // each click turns the caster a quarter clockwise before it fires
+    pub turn_on_click: bool,
+    // ticks between two quarter turns on a timer, never 0
+    pub turn_ticks: Option<u32>,
+    // starting with the caster's own tile
+    pub rail: Rail,
+}
+
+// Tiles something slides along and back again, in world coordinates. Authored
+// with the entity fields `rail`, a list of points, and `rail_step`, the
+// seconds spent on each tile.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Rail {
+    pub tiles: Vec<(i32, i32)>,
+    // ticks spent on each tile, taken as 1 when 0
+    pub ticks: u32,
+}
+
+impl Rail {
+    // the rail of an entity starting on its own tile
+    pub fn from_entity(entity: &EntityInstance, rect: LevelRect) -> Self {
+        let start = (rect.x + entity.grid[0], rect.y + entity.grid[1]);
+        let waypoints = entity
+            .field("rail")
+            .and_then(|value| value.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|point| {
+                let x = point.get("cx")?.as_i64()? as i32;
+                let y = point.get("cy")?.as_i64()? as i32;
+                Some((rect.x + x, rect.y + y))
+            });
+        Rail {
+            tiles: rail_tiles(start, waypoints),
+            ticks: seconds_to_ticks(
+                entity
+                    .field("rail_step")
+                    .and_then(|value| value.as_f64())
+                    .unwrap_or(1.),
+            ),
+        }
+    }
+
+    // the tile on `tick`, back and forth with the ends visited once per pass
+    pub fn at(&self, tick: u32) -> (i32, i32) {
+        let len = self.tiles.len() as u32;
+        let cycle = (2 * len).saturating_sub(2).max(1);
+        let step = (tick / self.ticks.max(1)) % cycle;
+        let index = if step < len { step } else { cycle - step };
+        self.tiles[index as usize]
+    }
+}
+
+// Caster_motion entities of a level, keyed by the world tile of their caster
+pub fn caster_motions(level: &Level) -> HashMap<(i32, i32), CasterMotion> {
+    let rect = level.tile_rect();
+    level
+        .entities("Caster_motion")
+        .map(|entity| {
+            let start = (rect.x + entity.grid[0], rect.y + entity.grid[1]);
+            let motion = CasterMotion {
+                turn_on_click: entity
+                    .field("turn_on_click")
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or_default(),
+                turn_ticks: entity
+                    .field("turn_every")
+                    .and_then(|value| value.as_f64())
+                    .map(seconds_to_ticks)
+                    .filter(|ticks| *ticks > 0),
+                rail: Rail::from_entity(entity, rect),
+            };
+            (start, motion)
+        })
+        .collect()
+}
+
+// every tile from `start` through the waypoints, straight lines are walked
+// along x first and then along y
+fn rail_tiles(start: (i32, i32), waypoints: impl Iterator<Item = (i32, i32)>) -> Vec<(i32, i32)> {
+    let mut tiles = vec![start];
+    let (mut x, mut y) = start;
+    for (to_x, to_y) in waypoints {
+        while (x, y) != (to_x, to_y) {
+            if x != to_x {
+                x += (to_x - x).signum();
+            } else {
+                y += (to_y - y).signum();
+            }
+            tiles.push((x, y));
+        }
+    }
+    tiles
+}
+
+// Components
+
+#[derive(Component, Clone)]
+pub struct MovingCaster {
+    motion: CasterMotion,
+    start_dir: Dir,
+    // quarter turns from clicks so far
+    clicks: u32,
+}
+
+impl MovingCaster {
+    pub fn new(motion: CasterMotion, start_dir: Dir) -> Self {
+        MovingCaster {
+            motion,
+            start_dir,
+            clicks: 0,
+        }
+    }
+
+    pub fn start_tile(&self) -> (i32, i32) {
+        self.motion.rail.at(0)
+    }
+
+    pub fn turns_on_click(&self) -> bool {
+        self.motion.turn_on_click
+    }
+
+    // where the caster is and where it points on `tick`
+    pub fn pose(&self, tick: u32) -> RayCaster {
+        let timer_turns = self.motion.turn_ticks.map_or(0, |ticks| tick / ticks);
+        let (pos_x, pos_y) = self.motion.rail.at(tick);
+        RayCaster {
+            dir: self.start_dir.turned(timer_turns + self.clicks),
+            pos_x,
+            pos_y,
+        }
+    }
+}
+
+// a ray fired by a caster, re-traced whenever the grid changes
+#[derive(Component)]
+pub struct Beam {
+    pub caster: Entity,
+}
+
+// Plugin
+
+pub struct CasterPlugin;
+
+impl Plugin for CasterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            move_casters
+                .before(move_player)
+                .in_set(SimulationSets::Step)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(slide_casters.in_set(GameSystemSets::Logic));
+    }
+}
+
+// turns a caster that turns on click, before it fires
+pub fn click_caster(
+    ray_caster: &mut RayCaster,
+    moving: Option<&mut MovingCaster>,
+    grid: &mut LevelGrid,
+) {
+    let Some(moving) = moving.filter(|moving| moving.turns_on_click()) else {
+        return;
+    };
+    moving.clicks += 1;
+    ray_caster.dir = ray_caster.dir.turned(1);
+    place_caster(ray_caster, ray_caster.clone(), grid);
+}
+
+// moves a caster in the grid, so beams stop at its new tile
+pub fn place_caster(ray_caster: &mut RayCaster, pose: RayCaster, grid: &mut LevelGrid) {
+    grid.set(ray_caster.pos_x, ray_caster.pos_y, 0);
+    grid.set(pose.pos_x, pose.pos_y, caster_value(&pose.dir));
+    *ray_caster = pose;
+}
+
+// puts moving casters where they are on this tick and re-traces every beam
+// once the grid changed, here or by clicks and streaming
+pub fn move_casters(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    game_assets: Res<GameAssets>,
+    mut grid: ResMut<LevelGrid>,
+    mut q_caster: Query<(&mut RayCaster, Option<&MovingCaster>)>,
+    mut q_beam: Query<(Entity, &Beam, &mut Ray)>,
+) {
+    let mut changed = grid.is_changed();
+    for (mut ray_caster, moving) in &mut q_caster {
+        let Some(moving) = moving else {
+            continue;
+        };
+        let pose = moving.pose(game_state.tick);
+        if pose != *ray_caster {
+            place_caster(&mut ray_caster, pose, &mut grid);
+            changed = true;
+        }
+    }
+    if !changed {
+        return;
+    }
+
+    for (entity, beam, mut ray) in &mut q_beam {
+        let Ok((ray_caster, _)) = q_caster.get(beam.caster) else {
+            continue;
+        };
+        let traced = retrace_ray(&ray, ray_caster, &grid);
+        if traced == *ray {
+            continue;
+        }
+        let color = ray_color(traced.prio);
+        let sprites = spawn_ray_sprites(&traced, color, &game_assets, &mut commands);
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).push_children(&sprites);
+        *ray = traced;
+    }
+}
+
+// moving casters glide to their tile and turn towards their direction, sprites
+// are drawn for the direction the caster starts with
+fn slide_casters(
+    time: Res<Time>,
+    mut q_caster: Query<(&RayCaster, &MovingCaster, &mut Transform)>,
+) {
+    let t = (time.delta_seconds() * SLIDE_SPEED).min(1.);
+    for (ray_caster, moving, mut transform) in &mut q_caster {
+        let target = Vec3::new(
+            ray_caster.pos_x as f32 * TILE_SIZE,
+            -ray_caster.pos_y as f32 * TILE_SIZE,
+            transform.translation.z,
+        );
+        transform.translation = transform.translation.lerp(target, t);
+        let rotation = Quat::from_rotation_z(ray_caster.dir.angle() - moving.start_dir.angle());
+        transform.rotation = transform.rotation.slerp(rotation, t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caster(motion: CasterMotion) -> MovingCaster {
+        MovingCaster::new(motion, Dir::Rightwards)
+    }
+
+    #[test]
+    fn rails_are_walked_tile_by_tile() {
+        let tiles = rail_tiles((0, 0), [(2, 0), (2, 2), (1, 3)].into_iter());
+        assert_eq!(
+            tiles,
+            vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (1, 3)]
+        );
+    }
+
+    #[test]
+    fn casters_slide_back_and_forth_along_the_rail() {
+        let moving = caster(CasterMotion {
+            rail: Rail {
+                tiles: vec![(0, 0), (1, 0), (2, 0)],
+                ticks: 10,
+            },
+            ..Default::default()
+        });
+        let tiles: Vec<i32> = (0..9).map(|step| moving.pose(step * 10).pos_x).collect();
+        assert_eq!(tiles, vec![0, 1, 2, 1, 0, 1, 2, 1, 0]);
+        assert_eq!(moving.pose(19).pos_x, 1);
+    }
+
+    #[test]
+    fn timer_and_clicks_add_up_to_quarter_turns() {
+        let mut moving = caster(CasterMotion {
+            turn_on_click: true,
+            turn_ticks: Some(30),
+            rail: Rail {
+                tiles: vec![(4, 4)],
+                ticks: 1,
+            },
+        });
+        assert!(moving.pose(29).dir == Dir::Rightwards);
+        assert!(moving.pose(30).dir == Dir::Downwards);
+        assert!(moving.pose(60).dir == Dir::Leftwards);
+
+        let mut ray_caster = moving.pose(30);
+        let mut grid = LevelGrid::default();
+        click_caster(&mut ray_caster, Some(&mut moving), &mut grid);
+        assert!(ray_caster.dir == Dir::Leftwards);
+        assert!(moving.pose(30) == ray_caster);
+        assert!(moving.pose(90).dir == Dir::Rightwards);
+    }
+
+    #[test]
+    fn clicks_only_turn_casters_that_ask_for_it() {
+        let mut moving = caster(CasterMotion {
+            rail: Rail {
+                tiles: vec![(4, 4)],
+                ticks: 1,
+            },
+            ..Default::default()
+        });
+        let mut ray_caster = moving.pose(0);
+        click_caster(
+            &mut ray_caster,
+            Some(&mut moving),
+            &mut LevelGrid::default(),
+        );
+        assert!(ray_caster.dir == Dir::Rightwards);
+    }
+}
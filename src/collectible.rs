@@ -0,0 +1,167 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{
+    check_goal,
+    level::Level,
+    loading::GameAssets,
+    move_player,
+    world::{CurrentLevel, LevelEntity},
+    GameState, GameSystemSets, Player, SimulationSets, TILE_SIZE,
+};
+
+// turns per second of collectible sprites
+const SPIN_SPEED: f32 = 0.25;
+const COLLECTIBLE_SIZE: f32 = 0.5;
+
+// Collectibles
+//
+// Collectible entities are picked up when the ship's closest tile is theirs.
+// They never change how the ship moves, so replays play the same with or
+// without them. Levels with the level field `collect_all` ask for all of them
+// in a single run that reaches the goal, an optional objective shown on level
+// complete. Progress is kept per level in the save.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectibleKind {
+    FuelCell,
+    StarFragment,
+}
+
+impl CollectibleKind {
+    fn color(&self) -> Color {
+        match self {
+            CollectibleKind::FuelCell => Color::rgb(0.4, 1., 0.6),
+            CollectibleKind::StarFragment => Color::rgb(1., 0.85, 0.25),
+        }
+    }
+}
+
+// Components
+
+// in world tile coordinates
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Collectible {
+    pub x: i32,
+    pub y: i32,
+    pub kind: CollectibleKind,
+}
+
+// Plugin
+
+pub struct CollectiblePlugin;
+
+impl Plugin for CollectiblePlugin {
+    fn build(&self, app: &mut App) {
+        // picked up before check_goal, so one on the goal tile still counts
+        app.add_system(
+            pick_up
+                .after(move_player)
+                .before(check_goal)
+                .in_set(SimulationSets::Step)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(show_collectibles.in_set(GameSystemSets::Logic));
+    }
+}
+
+pub fn collectibles(level: &Level) -> Vec<Collectible> {
+    let rect = level.tile_rect();
+    level
+        .entities("Collectible")
+        .map(|entity| Collectible {
+            x: rect.x + entity.grid[0],
+            y: rect.y + entity.grid[1],
+            kind: match entity.field("kind").and_then(|value| value.as_str()) {
+                Some("Star_fragment") => CollectibleKind::StarFragment,
+                _ => CollectibleKind::FuelCell,
+            },
+        })
+        .collect()
+}
+
+// the optional objective of the level
+pub fn wants_all(level: &Level) -> bool {
+    level
+        .field("collect_all")
+        .and_then(|value| value.as_bool())
+        .unwrap_or_default()
+}
+
+pub fn spawn_collectibles(
+    level: &Level,
+    game_assets: &GameAssets,
+    level_entity: &LevelEntity,
+    commands: &mut Commands,
+) {
+    for collectible in collectibles(level) {
+        commands.spawn((
+            SpriteBundle {
+                texture: game_assets.tile.clone(),
+                sprite: Sprite {
+                    color: collectible.kind.color(),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(
+                    collectible.x as f32 * TILE_SIZE,
+                    -collectible.y as f32 * TILE_SIZE,
+                    150.,
+                )
+                .with_scale(Vec3::splat(COLLECTIBLE_SIZE)),
+                ..Default::default()
+            },
+            collectible,
+            level_entity.clone(),
+        ));
+    }
+}
+
+fn pick_up(
+    mut game_state: ResMut<GameState>,
+    q_player: Query<&Player>,
+    q_collectible: Query<&Collectible>,
+) {
+    for player in &q_player {
+        let tile = player.tile();
+        let found = q_collectible
+            .iter()
+            .any(|collectible| (collectible.x, collectible.y) == tile);
+        if found && !game_state.collected.contains(&tile) {
+            game_state.collected.push(tile);
+        }
+    }
+}
+
+// hides what was picked up since entering the level and spins the rest
+fn show_collectibles(
+    time: Res<Time>,
+    game_state: Res<GameState>,
+    mut q_collectible: Query<(&Collectible, &mut Visibility, &mut Transform)>,
+) {
+    let turn = time.delta_seconds() * SPIN_SPEED * TAU;
+    for (collectible, mut visibility, mut transform) in &mut q_collectible {
+        let picked = game_state
+            .collected
+            .contains(&(collectible.x, collectible.y));
+        *visibility = if picked {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        transform.rotate_z(turn);
+    }
+}
+
+// level local tiles of the collectibles of the current level picked up so far
+pub fn collected_here(game_state: &GameState, current: &CurrentLevel) -> Vec<(i32, i32)> {
+    let rect = current.rect;
+    let mut collected: Vec<(i32, i32)> = game_state
+        .collected
+        .iter()
+        .filter(|(x, y)| rect.contains(*x, *y))
+        .map(|(x, y)| (x - rect.x, y - rect.y))
+        .collect();
+    collected.sort();
+    collected
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    hazards::GravityWell,
+    level::{Level, LevelRect},
+    loading::GameAssets,
+    physics::{step_ship, ShipMode},
+    spawn_player,
+    world::start_positions,
+    AppState, Dir, GameState, GameSystemSets, LevelGrid, Player, Ray, END_TILE,
+};
+
+const HUD_FONT_SIZE: f32 = 16.;
+
+// Fleets
+//
+// Levels get a ship on every Start tile. A Ship entity on a Start tile gives
+// the ship there its own End tile with the `goal` field, the others may end
+// on any End tile of the level. The level is solved once every ship reached
+// its goal, in any order. Ships step one after another in the order of their
+// Start tiles, row by row, and never move onto a tile another ship is on or
+// moving onto: grid ships wait on their centre and sailing ships stop as if
+// the tile was an obstacle. Beams carry every ship the same and losing any
+// ship restarts the level.
+
+// Components
+
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct FleetMember {
+    // position of the ship's Start tile in reading order
+    pub index: usize,
+    // world tile, None for any End tile
+    pub goal: Option<(i32, i32)>,
+}
+
+impl FleetMember {
+    // the ship is on its goal in the level covering `rect`
+    pub fn on_goal(&self, player: &Player, grid: &LevelGrid, rect: LevelRect) -> bool {
+        let (x, y) = player.tile();
+        grid.get(x, y) == END_TILE
+            && rect.contains(x, y)
+            && self.goal.is_none_or(|goal| goal == (x, y))
+    }
+}
+
+#[derive(Component)]
+struct FleetHud;
+
+// Plugin
+
+pub struct FleetPlugin;
+
+impl Plugin for FleetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(update_fleet_hud.in_set(GameSystemSets::Logic));
+        app.add_system(despawn_fleet_hud.in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+// where the ships of a level start, in world tile coordinates, in fleet order
+pub fn fleet(level: &Level) -> Vec<((f32, f32), FleetMember)> {
+    let rect = level.tile_rect();
+    let goals: HashMap<(i32, i32), (i32, i32)> = level
+        .entities("Ship")
+        .filter_map(|entity| {
+            let goal = entity.field("goal")?;
+            let x = goal.get("cx")?.as_i64()? as i32;
+            let y = goal.get("cy")?.as_i64()? as i32;
+            let start = (rect.x + entity.grid[0], rect.y + entity.grid[1]);
+            Some((start, (rect.x + x, rect.y + y)))
+        })
+        .collect();
+    start_positions(level)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (x, y))| {
+            let goal = goals.get(&(x as i32, y as i32)).copied();
+            ((x, y), FleetMember { index, goal })
+        })
+        .collect()
+}
+
+pub fn spawn_fleet(level: &Level, game_assets: &GameAssets, commands: &mut Commands) {
+    for ((x, y), member) in fleet(level) {
+        let ship = spawn_player(x, y, game_assets, commands);
+        commands.entity(ship).insert(member);
+    }
+}
+
+// steps every ship of `ships`, given in fleet order, each making way for the
+// others where they are after their own step
+pub fn step_fleet<'a, I>(
+    ships: &mut [Player],
+    rays: I,
+    mode: ShipMode,
+    grid: &LevelGrid,
+    wells: &[GravityWell],
+    tick: u32,
+    delta: f32,
+) where
+    I: IntoIterator<Item = &'a Ray> + Clone,
+{
+    for index in 0..ships.len() {
+        let others: Vec<(i32, i32)> = ships
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, ship)| ship.claims())
+            .collect();
+        let rays = rays.clone();
+        step_ship(
+            &mut ships[index],
+            rays,
+            mode,
+            grid,
+            wells,
+            &others,
+            tick,
+            delta,
+        );
+    }
+}
+
+impl Dir {
+    fn name(&self) -> &'static str {
+        match self {
+            Dir::Upwards => "up",
+            Dir::Downwards => "down",
+            Dir::Leftwards => "left",
+            Dir::Rightwards => "right",
+        }
+    }
+}
+
+// one line of the HUD
+fn ship_status(index: usize, player: &Player, arrived: bool) -> String {
+    let state = match (&player.direction, arrived) {
+        (_, true) => "arrived".to_string(),
+        (Some(direction), false) => format!("flying {}", direction.name()),
+        (None, false) => "waiting".to_string(),
+    };
+    format!("ship {}  {}", index + 1, state)
+}
+
+// lists the ships of levels with more than one
+fn update_fleet_hud(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    game_assets: Res<GameAssets>,
+    q_ship: Query<(&Player, &FleetMember)>,
+    mut q_hud: Query<(Entity, &mut Text), With<FleetHud>>,
+) {
+    let mut ships: Vec<(&Player, &FleetMember)> = q_ship.iter().collect();
+    ships.sort_by_key(|(_, member)| member.index);
+    if ships.len() < 2 {
+        for (entity, _) in &q_hud {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let style = TextStyle {
+        font: game_assets.font.clone(),
+        font_size: HUD_FONT_SIZE,
+        color: Color::WHITE,
+    };
+    let sections: Vec<TextSection> = ships
+        .iter()
+        .map(|(player, member)| {
+            let arrived = game_state.arrived.contains(&member.index);
+            let line = ship_status(member.index, player, arrived);
+            TextSection::new(line + "\n", style.clone())
+        })
+        .collect();
+    match q_hud.get_single_mut() {
+        Ok((_, mut text)) => text.sections = sections,
+        Err(_) => {
+            commands.spawn((
+                TextBundle::from_sections(sections).with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(12.),
+                        top: Val::Px(12.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }),
+                FleetHud,
+            ));
+        }
+    }
+}
+
+fn despawn_fleet_hud(mut commands: Commands, q_hud: Query<Entity, With<FleetHud>>) {
+    for entity in &q_hud {
+        commands.entity(entity).despawn_recursive();
+    }
+}
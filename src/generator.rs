@@ -0,0 +1,514 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::asset::FileAssetIo;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    level::Level,
+    loading::PROJECT_PATH,
+    score::Par,
+    solver::{solve, Solution},
+    world::START_TILE,
+    END_TILE,
+};
+
+const OBSTACLE_TILE: i32 = 1;
+// casters facing down, up, right and left
+const CASTER_TILES: [i32; 4] = [2, 3, 4, 5];
+// empty tiles between generated levels and the rest of the LDtk world
+const WORLD_GAP: i32 = 2;
+
+// Level generator
+//
+// Builds puzzles from a seed: a border of obstacles with casters in it
+// facing inwards, obstacles and casters scattered inside, one Start and one
+// End tile. Only levels the solver finds exactly one shortest solution for,
+// with the wanted number of clicks, are kept. They are written as .ldtkl
+// files shaped like a level of the project, so LDtk opens them like any
+// other level.
+
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    // level size in tiles, border included
+    pub width: i32,
+    pub height: i32,
+    // obstacles and casters inside the border
+    pub obstacles: usize,
+    pub casters: usize,
+    // casters in the border
+    pub border_casters: usize,
+    // the difficulty, clicks of the shortest solution
+    pub clicks: usize,
+    // layouts tried before giving up
+    pub attempts: u32,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            width: 10,
+            height: 10,
+            obstacles: 8,
+            casters: 2,
+            border_casters: 6,
+            clicks: 3,
+            attempts: 2000,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    // levels need a tile inside their border for every obstacle, caster, the
+    // Start and the End, and a border tile but the corners for every border
+    // caster
+    pub fn check(&self) -> Result<(), GenerateError> {
+        if self.width < 3 || self.height < 3 {
+            return Err(GenerateError::TooSmall(self.width, self.height));
+        }
+        let inside = ((self.width - 2) * (self.height - 2)) as usize;
+        let wanted = self.obstacles + self.casters + 2;
+        if wanted > inside {
+            return Err(GenerateError::TooCrowded(wanted, inside));
+        }
+        let border = (2 * (self.width - 2) + 2 * (self.height - 2)) as usize;
+        if self.border_casters > border {
+            return Err(GenerateError::BorderCrowded(self.border_casters, border));
+        }
+        Ok(())
+    }
+}
+
+// splitmix64, small and the same on every platform so seeds keep their levels
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // takes a random element out of `items`
+    fn take<T>(&mut self, items: &mut Vec<T>) -> Option<T> {
+        if items.is_empty() {
+            return None;
+        }
+        let index = self.below(items.len());
+        Some(items.swap_remove(index))
+    }
+
+    // random hex in the shape of a version 4 uuid, like LDtk's iids
+    fn uuid(&mut self) -> String {
+        let (a, b) = (self.next(), self.next());
+        format!(
+            "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+            a >> 32,
+            (a >> 16) & 0xffff,
+            a & 0xfff,
+            0x8000 | ((b >> 48) & 0x3fff),
+            b & 0xffff_ffff_ffff
+        )
+    }
+}
+
+// A generated level and how it is solved
+pub struct Generated {
+    pub level: Value,
+    pub solution: Solution,
+}
+
+// the IntGrid of one layout, row by row
+fn layout(config: &GeneratorConfig, rng: &mut Rng) -> Vec<i32> {
+    debug_assert!(config.check().is_ok());
+    let (width, height) = (config.width, config.height);
+    let mut tiles = vec![0; (width * height) as usize];
+    let index = |x: i32, y: i32| (x + width * y) as usize;
+
+    // border tiles but the corners, with the caster facing inwards
+    let mut border = Vec::new();
+    for x in 1..width - 1 {
+        border.push((x, 0, CASTER_TILES[0]));
+        border.push((x, height - 1, CASTER_TILES[1]));
+    }
+    for y in 1..height - 1 {
+        border.push((0, y, CASTER_TILES[2]));
+        border.push((width - 1, y, CASTER_TILES[3]));
+    }
+    for y in 0..height {
+        for x in 0..width {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                tiles[index(x, y)] = OBSTACLE_TILE;
+            }
+        }
+    }
+    for _ in 0..config.border_casters {
+        if let Some((x, y, caster)) = rng.take(&mut border) {
+            tiles[index(x, y)] = caster;
+        }
+    }
+
+    let mut inside: Vec<(i32, i32)> = (1..height - 1)
+        .flat_map(|y| (1..width - 1).map(move |x| (x, y)))
+        .collect();
+    let mut scattered = vec![START_TILE, END_TILE];
+    scattered.extend(std::iter::repeat_n(OBSTACLE_TILE, config.obstacles));
+    for _ in 0..config.casters {
+        scattered.push(CASTER_TILES[rng.below(CASTER_TILES.len())]);
+    }
+    for value in scattered {
+        if let Some((x, y)) = rng.take(&mut inside) {
+            tiles[index(x, y)] = value;
+        }
+    }
+    tiles
+}
+
+// `template` turned into the level `identifier` with the given IntGrid,
+// without entities and with its level fields left empty
+fn level_file(
+    template: &Value,
+    identifier: &str,
+    uid: i64,
+    (world_x, world_y): (i32, i32),
+    config: &GeneratorConfig,
+    tiles: &[i32],
+    rng: &mut Rng,
+) -> Value {
+    let mut level = template.clone();
+    let grid_size = template["layerInstances"][0]["__gridSize"]
+        .as_i64()
+        .unwrap_or(16) as i32;
+    level["identifier"] = json!(identifier);
+    level["iid"] = json!(rng.uuid());
+    level["uid"] = json!(uid);
+    level["useAutoIdentifier"] = json!(false);
+    level["worldX"] = json!(world_x);
+    level["worldY"] = json!(world_y);
+    level["pxWid"] = json!(config.width * grid_size);
+    level["pxHei"] = json!(config.height * grid_size);
+    level["externalRelPath"] = Value::Null;
+    level["__neighbours"] = json!([]);
+
+    // LDtk keeps what was typed into a field next to its value
+    for field in level["fieldInstances"].as_array_mut().into_iter().flatten() {
+        if field["__type"] == "Bool" {
+            field["__value"] = json!(false);
+            field["realEditorValues"] = json!([]);
+        } else {
+            field["__value"] = Value::Null;
+            field["realEditorValues"] = json!([null]);
+        }
+    }
+    if let Some(layers) = level["layerInstances"].as_array_mut() {
+        for layer in layers {
+            let int_grid = layer["__type"] == "IntGrid";
+            layer["__cWid"] = json!(config.width);
+            layer["__cHei"] = json!(config.height);
+            layer["iid"] = json!(rng.uuid());
+            layer["levelId"] = json!(uid);
+            layer["intGridCsv"] = if int_grid { json!(tiles) } else { json!([]) };
+            layer["entityInstances"] = json!([]);
+            layer["autoLayerTiles"] = json!([]);
+            layer["gridTiles"] = json!([]);
+        }
+    }
+    level
+}
+
+// fills in the level fields `par_rays` and `par_time` of a level file
+fn set_par(level: &mut Value, par: Par) {
+    for field in level["fieldInstances"].as_array_mut().into_iter().flatten() {
+        let (value, editor) = match field["__identifier"].as_str() {
+            Some("par_rays") => (json!(par.rays), "V_Int"),
+            // as LDtk would write the f32 typed in
+            Some("par_time") => (
+                json!(par.time.map(|time| (time as f64 * 10.).round() / 10.)),
+                "V_Float",
+            ),
+            _ => continue,
+        };
+        field["realEditorValues"] = json!([{ "id": editor, "params": [value] }]);
+        field["__value"] = value;
+    }
+}
+
+// JSON indented with tabs like LDtk writes it
+fn to_ldtk_json(value: &Value) -> Result<Vec<u8>, serde_json::Error> {
+    let mut json = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"\t");
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut json, formatter,
+    ))?;
+    Ok(json)
+}
+
+// the first level of `seed` that fits `config`, shaped like `template`
+pub fn generate(
+    config: &GeneratorConfig,
+    seed: u64,
+    template: &Value,
+    identifier: &str,
+    uid: i64,
+    world_position: (i32, i32),
+) -> Option<Generated> {
+    let mut rng = Rng(seed);
+    for _ in 0..config.attempts {
+        let tiles = layout(config, &mut rng);
+        let mut file = level_file(
+            template,
+            identifier,
+            uid,
+            world_position,
+            config,
+            &tiles,
+            &mut rng,
+        );
+        let level: Level = serde_json::from_value(file.clone()).ok()?;
+        let Some(solution) = solve(&level, std::slice::from_ref(&level), config.clicks) else {
+            continue;
+        };
+        if solution.clicks() != config.clicks || solution.count != 1 {
+            continue;
+        }
+        set_par(&mut file, solution.par());
+        return Some(Generated {
+            level: file,
+            solution,
+        });
+    }
+    None
+}
+
+#[derive(Debug)]
+pub enum GenerateError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    // the project has no level to shape generated ones after
+    NoTemplate,
+    // a level with this identifier is already in the project
+    Exists(String),
+    // none of the attempts had a unique solution with the wanted clicks
+    NotFound(u32),
+    // width and height, a level needs at least one tile inside its border
+    TooSmall(i32, i32),
+    // tiles needed inside the border and tiles there are
+    TooCrowded(usize, usize),
+    // border casters wanted and border tiles there are without the corners
+    BorderCrowded(usize, usize),
+}
+
+impl From<std::io::Error> for GenerateError {
+    fn from(error: std::io::Error) -> Self {
+        GenerateError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for GenerateError {
+    fn from(error: serde_json::Error) -> Self {
+        GenerateError::Json(error)
+    }
+}
+
+impl std::fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerateError::Io(error) => write!(f, "{}", error),
+            GenerateError::Json(error) => write!(f, "invalid project: {}", error),
+            GenerateError::NoTemplate => write!(f, "the project has no level to start from"),
+            GenerateError::Exists(identifier) => {
+                write!(f, "{} is already in the project", identifier)
+            }
+            GenerateError::NotFound(attempts) => write!(
+                f,
+                "no level with a unique solution in {} attempts, try another seed",
+                attempts
+            ),
+            GenerateError::TooSmall(width, height) => write!(
+                f,
+                "a {}x{} level is too small, levels are at least 3x3",
+                width, height
+            ),
+            GenerateError::TooCrowded(wanted, inside) => write!(
+                f,
+                "{} obstacles, casters, start and end do not fit on the {} tiles inside the border",
+                wanted, inside
+            ),
+            GenerateError::BorderCrowded(wanted, border) => write!(
+                f,
+                "{} border casters do not fit on the {} border tiles between the corners",
+                wanted, border
+            ),
+        }
+    }
+}
+
+// Generates the level of `seed` into the project at `project_path`: the
+// level file goes next to the others and the project lists it to the right
+// of the existing levels. Returns the level file written.
+pub fn add_generated_level(
+    project_path: &Path,
+    config: &GeneratorConfig,
+    seed: u64,
+) -> Result<(PathBuf, Generated), GenerateError> {
+    config.check()?;
+    let mut project: Value = serde_json::from_slice(&fs::read(project_path)?)?;
+    let project_dir = project_path.parent().unwrap_or(Path::new(""));
+    let identifier = format!("Generated_{}", seed);
+    let levels = project["levels"].as_array().cloned().unwrap_or_default();
+    if levels.iter().any(|level| level["identifier"] == identifier) {
+        return Err(GenerateError::Exists(identifier));
+    }
+
+    // the first level with its layers, saved on its own or in the project
+    let (template, rel_dir) = match levels.first() {
+        Some(level) => match level["externalRelPath"].as_str() {
+            Some(rel_path) => {
+                let template = serde_json::from_slice(&fs::read(project_dir.join(rel_path))?)?;
+                let rel_dir = Path::new(rel_path).parent().map(Path::to_path_buf);
+                (template, rel_dir)
+            }
+            None => (level.clone(), None),
+        },
+        None => return Err(GenerateError::NoTemplate),
+    };
+    let grid_size = project["defaultGridSize"].as_i64().unwrap_or(16) as i32;
+    let world_x = levels
+        .iter()
+        .filter_map(|level| Some(level["worldX"].as_i64()? + level["pxWid"].as_i64()?))
+        .max()
+        .unwrap_or_default() as i32
+        + WORLD_GAP * grid_size;
+    let uid = project["nextUid"].as_i64().unwrap_or_default();
+
+    let generated = generate(config, seed, &template, &identifier, uid, (world_x, 0))
+        .ok_or(GenerateError::NotFound(config.attempts))?;
+    let mut entry = generated.level.clone();
+    let path = match rel_dir {
+        Some(rel_dir) => {
+            let rel_path = rel_dir.join(&identifier).with_extension("ldtkl");
+            let path = project_dir.join(&rel_path);
+            fs::write(&path, to_ldtk_json(&generated.level)?)?;
+            entry["layerInstances"] = Value::Null;
+            entry["externalRelPath"] = json!(rel_path.to_string_lossy().replace('\\', "/"));
+            path
+        }
+        None => project_path.to_path_buf(),
+    };
+    if let Some(levels) = project["levels"].as_array_mut() {
+        levels.push(entry);
+    }
+    project["nextUid"] = json!(uid + 1);
+    fs::write(project_path, to_ldtk_json(&project)?)?;
+    Ok((path, generated))
+}
+
+// `--generate <seed> [<width>x<height>] [<clicks>]`, prints the outcome and
+// returns the exit code
+pub fn generate_command(args: &[String]) -> i32 {
+    const USAGE: &str = "usage: --generate <seed> [<width>x<height>] [<clicks>]";
+    let mut config = GeneratorConfig::default();
+    let Some(seed) = args.first().and_then(|seed| seed.parse().ok()) else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    for arg in &args[1..] {
+        match arg.split_once('x') {
+            Some((width, height)) => {
+                config.width = width.parse().unwrap_or(config.width);
+                config.height = height.parse().unwrap_or(config.height);
+            }
+            None => config.clicks = arg.parse().unwrap_or(config.clicks),
+        }
+    }
+    if let Err(error) = config.check() {
+        eprintln!("{}\n{}", error, USAGE);
+        return 2;
+    }
+
+    let project = FileAssetIo::get_base_path()
+        .join("assets")
+        .join(PROJECT_PATH);
+    match add_generated_level(&project, &config, seed) {
+        Ok((path, generated)) => {
+            println!(
+                "{}: {} clicks, solved after {} ticks",
+                path.display(),
+                generated.solution.clicks(),
+                generated.solution.ticks
+            );
+            0
+        }
+        Err(error) => {
+            eprintln!("could not generate a level: {}", error);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_need_room_inside_the_border() {
+        let sized = |width, height| GeneratorConfig {
+            width,
+            height,
+            ..Default::default()
+        };
+        assert!(GeneratorConfig::default().check().is_ok());
+        for (width, height) in [(2, 10), (10, 2), (-4, 10), (0, 0)] {
+            assert!(matches!(
+                sized(width, height).check(),
+                Err(GenerateError::TooSmall(..))
+            ));
+        }
+        // 8 obstacles, 2 casters, start and end
+        assert!(sized(4, 8).check().is_ok());
+        assert!(matches!(
+            sized(4, 7).check(),
+            Err(GenerateError::TooCrowded(12, 10))
+        ));
+        // 2 + 2 + 6 + 6 tiles between the corners
+        let border = |border_casters| GeneratorConfig {
+            border_casters,
+            ..sized(4, 8)
+        };
+        assert!(border(16).check().is_ok());
+        assert!(matches!(
+            border(17).check(),
+            Err(GenerateError::BorderCrowded(17, 16))
+        ));
+        assert_eq!(generate_command(&["1".to_string(), "-3x5".to_string()]), 2);
+        assert_eq!(generate_command(&["1".to_string(), "4x4".to_string()]), 2);
+    }
+
+    #[test]
+    fn layouts_have_a_border_and_one_start_and_end() {
+        let config = GeneratorConfig::default();
+        let mut rng = Rng(7);
+        for _ in 0..50 {
+            let tiles = layout(&config, &mut rng);
+            let count = |value| tiles.iter().filter(|tile| **tile == value).count();
+            assert_eq!((count(START_TILE), count(END_TILE)), (1, 1));
+            let casters = tiles.iter().filter(|tile| CASTER_TILES.contains(tile));
+            assert_eq!(casters.count(), config.border_casters + config.casters);
+            for (index, tile) in tiles.iter().enumerate() {
+                let (x, y) = (index as i32 % config.width, index as i32 / config.width);
+                if x == 0 || y == 0 || x == config.width - 1 || y == config.height - 1 {
+                    assert!(*tile == OBSTACLE_TILE || CASTER_TILES.contains(tile));
+                }
+            }
+        }
+    }
+}
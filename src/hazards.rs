@@ -0,0 +1,314 @@
+use bevy::prelude::*;
+
+use crate::{
+    caster::{move_casters, Rail},
+    check_goal,
+    level::{EntityInstance, Level, LevelRect},
+    loading::GameAssets,
+    world::LevelEntity,
+    Dir, GameState, GameSystemSets, LevelGrid, Player, ShipLost, SimulationSets, TILE_SIZE,
+};
+
+// IntGrid value of black holes
+pub const BLACK_HOLE_TILE: i32 = 9;
+// not authored, written into the LevelGrid where an asteroid drifts
+pub const ASTEROID_TILE: i32 = 10;
+// tiles per second squared a well of strength 1 pulls a sailing ship a tile away
+const WELL_PULL: f32 = 4.;
+// closer to the centre of a well than this a sailing ship is caught and rests
+const WELL_CAPTURE: f32 = 0.5;
+// how quickly asteroid sprites catch up with their tile, per second
+const DRIFT_SPEED: f32 = 6.;
+
+// Hazards
+//
+// - black holes: IntGrid tiles that destroy the ship once its closest tile is
+//   one of them, beams pass over them
+// - asteroids: Asteroid entities drifting along a `rail` like moving casters,
+//   see caster.rs. They stop beams and sailing ships like obstacles and
+//   destroy ships they drift onto
+// - gravity wells: Gravity_well entities with a `strength` and a `radius` in
+//   tiles. Sailing ships within the radius are pulled towards the well's
+//   centre by strength * WELL_PULL / distance², at least a tile. Grid ships
+//   no lit ray carries on from a centre fall a tile towards the closest well
+//   in their row or column within the radius, unless something stops light
+//   there
+// Losing the ship restarts the level, see world.rs.
+
+// Components
+
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct GravityWell {
+    pub x: i32,
+    pub y: i32,
+    pub strength: f32,
+    pub radius: i32,
+}
+
+impl GravityWell {
+    fn from_entity(entity: &EntityInstance, rect: LevelRect) -> Self {
+        GravityWell {
+            x: rect.x + entity.grid[0],
+            y: rect.y + entity.grid[1],
+            strength: entity
+                .field("strength")
+                .and_then(|value| value.as_f64())
+                .unwrap_or(1.) as f32,
+            radius: entity
+                .field("radius")
+                .and_then(|value| value.as_i64())
+                .unwrap_or(3) as i32,
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Asteroid {
+    pub rail: Rail,
+    // the tile it takes up in the LevelGrid
+    pub tile: (i32, i32),
+}
+
+impl Asteroid {
+    pub fn new(rail: Rail) -> Self {
+        Asteroid {
+            tile: rail.at(0),
+            rail,
+        }
+    }
+}
+
+// Plugin
+
+pub struct HazardPlugin;
+
+impl Plugin for HazardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                drift_asteroids.before(move_casters),
+                check_hazards.after(check_goal),
+            )
+                .in_set(SimulationSets::Step)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(slide_asteroids.in_set(GameSystemSets::Logic));
+    }
+}
+
+pub fn gravity_wells(level: &Level) -> Vec<GravityWell> {
+    let rect = level.tile_rect();
+    level
+        .entities("Gravity_well")
+        .map(|entity| GravityWell::from_entity(entity, rect))
+        .collect()
+}
+
+pub fn asteroids(level: &Level) -> Vec<Asteroid> {
+    let rect = level.tile_rect();
+    level
+        .entities("Asteroid")
+        .map(|entity| Asteroid::new(Rail::from_entity(entity, rect)))
+        .collect()
+}
+
+// sprites for the black holes, wells and asteroids of a level
+pub fn spawn_hazards(
+    level: &Level,
+    game_assets: &GameAssets,
+    level_entity: &LevelEntity,
+    commands: &mut Commands,
+) {
+    let sprite = |(x, y): (i32, i32), color: Color, z: f32| SpriteBundle {
+        texture: game_assets.tile.clone(),
+        sprite: Sprite {
+            color,
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(x as f32 * TILE_SIZE, -y as f32 * TILE_SIZE, z),
+        ..Default::default()
+    };
+    let rect = level.tile_rect();
+    if let Some(layer) = level.int_grid() {
+        for (index, value) in layer.int_grid_csv.iter().enumerate() {
+            if *value != BLACK_HOLE_TILE {
+                continue;
+            }
+            let x = rect.x + index as i32 % layer.c_wid;
+            let y = rect.y + index as i32 / layer.c_wid;
+            commands.spawn((
+                sprite((x, y), Color::rgb(0.08, 0.02, 0.12), 100.),
+                level_entity.clone(),
+            ));
+        }
+    }
+    for well in gravity_wells(level) {
+        commands.spawn((
+            sprite((well.x, well.y), Color::rgba(0.6, 0.3, 1., 0.5), 90.),
+            well,
+            level_entity.clone(),
+        ));
+    }
+    for asteroid in asteroids(level) {
+        commands.spawn((
+            sprite(asteroid.tile, Color::rgb(0.55, 0.45, 0.35), 100.),
+            asteroid,
+            level_entity.clone(),
+        ));
+    }
+}
+
+// moves an asteroid in the grid, so beams stop at its new tile
+pub fn place_asteroid(asteroid: &mut Asteroid, tile: (i32, i32), grid: &mut LevelGrid) {
+    grid.set(asteroid.tile.0, asteroid.tile.1, 0);
+    grid.set(tile.0, tile.1, ASTEROID_TILE);
+    asteroid.tile = tile;
+}
+
+pub fn ship_lost(player: &Player, grid: &LevelGrid) -> bool {
+    let (x, y) = player.tile();
+    matches!(grid.get(x, y), BLACK_HOLE_TILE | ASTEROID_TILE)
+}
+
+// where wells send a grid ship standing on the centre of x, y, None to stop
+pub fn grid_pull(wells: &[GravityWell], grid: &LevelGrid, x: i32, y: i32) -> Option<Dir> {
+    wells
+        .iter()
+        .filter(|well| (well.x == x) != (well.y == y))
+        .filter_map(|well| {
+            let distance = (well.x - x).abs() + (well.y - y).abs();
+            let dir = match ((well.x - x).signum(), (well.y - y).signum()) {
+                (1, _) => Dir::Rightwards,
+                (-1, _) => Dir::Leftwards,
+                (_, 1) => Dir::Downwards,
+                _ => Dir::Upwards,
+            };
+            let (step_x, step_y) = dir.step();
+            let open = !grid.blocks_beam(x + step_x, y + step_y);
+            (distance <= well.radius && open).then_some((distance, dir))
+        })
+        .min_by_key(|(distance, dir)| (*distance, dir.clone() as u8))
+        .map(|(_, dir)| dir)
+}
+
+// tiles per second squared the wells pull a sailing ship at x, y with
+pub fn sail_pull(wells: &[GravityWell], x: f32, y: f32) -> Vec2 {
+    wells
+        .iter()
+        .map(|well| {
+            let offset = Vec2::new(well.x as f32 - x, well.y as f32 - y);
+            let distance = offset.length();
+            if distance > well.radius as f32 || distance < WELL_CAPTURE {
+                return Vec2::ZERO;
+            }
+            offset / distance * well.strength * WELL_PULL / distance.max(1.).powi(2)
+        })
+        .sum()
+}
+
+fn drift_asteroids(
+    game_state: Res<GameState>,
+    mut grid: ResMut<LevelGrid>,
+    mut q_asteroid: Query<&mut Asteroid>,
+) {
+    for mut asteroid in &mut q_asteroid {
+        let tile = asteroid.rail.at(game_state.tick);
+        // only touches the grid on a move, so beams are not re-traced every tick
+        if tile != asteroid.tile {
+            place_asteroid(&mut asteroid, tile, &mut grid);
+        }
+    }
+}
+
+fn slide_asteroids(time: Res<Time>, mut q_asteroid: Query<(&Asteroid, &mut Transform)>) {
+    let t = (time.delta_seconds() * DRIFT_SPEED).min(1.);
+    for (asteroid, mut transform) in &mut q_asteroid {
+        let (x, y) = asteroid.tile;
+        let target = Vec3::new(
+            x as f32 * TILE_SIZE,
+            -y as f32 * TILE_SIZE,
+            transform.translation.z,
+        );
+        transform.translation = transform.translation.lerp(target, t);
+    }
+}
+
+fn check_hazards(grid: Res<LevelGrid>, q_player: Query<&Player>, mut lost: EventWriter<ShipLost>) {
+    for player in &q_player {
+        if ship_lost(player, &grid) {
+            lost.send(ShipLost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn well(x: i32, y: i32, radius: i32) -> GravityWell {
+        GravityWell {
+            x,
+            y,
+            strength: 1.,
+            radius,
+        }
+    }
+
+    fn open_grid() -> LevelGrid {
+        LevelGrid {
+            rect: LevelRect {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            },
+            tiles: vec![0; 100],
+        }
+    }
+
+    #[test]
+    fn grid_ships_fall_towards_the_closest_well_in_line() {
+        let wells = [well(8, 5, 4), well(5, 3, 4), well(2, 2, 9)];
+        let grid = open_grid();
+        assert!(grid_pull(&wells, &grid, 5, 5) == Some(Dir::Upwards));
+        // diagonal wells and wells out of reach do not pull
+        assert!(grid_pull(&wells, &grid, 3, 4).is_none());
+        assert!(grid_pull(&wells[..1], &grid, 1, 5).is_none());
+        // a ship on a well stays there
+        assert!(grid_pull(&wells, &grid, 8, 5).is_none());
+    }
+
+    #[test]
+    fn obstacles_shield_grid_ships_from_wells() {
+        let mut grid = open_grid();
+        grid.set(5, 4, 1);
+        let wells = [well(5, 3, 4), well(8, 5, 4)];
+        assert!(grid_pull(&wells, &grid, 5, 5) == Some(Dir::Rightwards));
+    }
+
+    #[test]
+    fn wells_pull_sailing_ships_harder_up_close() {
+        let wells = [well(5, 5, 4)];
+        let far = sail_pull(&wells, 5., 2.);
+        let near = sail_pull(&wells, 5., 3.);
+        assert!(far.x == 0. && far.y > 0.);
+        assert!(near.y > far.y * 2.);
+        assert_eq!(sail_pull(&wells, 5., 0.), Vec2::ZERO);
+        assert_eq!(sail_pull(&wells, 5.2, 5.), Vec2::ZERO);
+    }
+
+    #[test]
+    fn drifting_asteroids_move_their_tile_in_the_grid() {
+        let mut grid = open_grid();
+        let mut asteroid = Asteroid::new(Rail {
+            tiles: vec![(2, 2), (3, 2)],
+            ticks: 10,
+        });
+        place_asteroid(&mut asteroid, (2, 2), &mut grid);
+        assert!(grid.blocks_beam(2, 2));
+        let tile = asteroid.rail.at(10);
+        place_asteroid(&mut asteroid, tile, &mut grid);
+        assert!(!grid.blocks_beam(2, 2));
+        assert!(grid.blocks_beam(3, 2));
+    }
+}
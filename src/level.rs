@@ -0,0 +1,309 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+// LDtk level files (.ldtkl), only the parts the game reads
+
+#[derive(Deserialize, Clone)]
+pub struct LayerInstance {
+    #[serde(rename = "__cHei")]
+    pub c_hei: i32,
+    #[serde(rename = "__cWid")]
+    pub c_wid: i32,
+    #[serde(rename = "__gridSize")]
+    pub grid_size: i32,
+    #[serde(rename = "intGridCsv")]
+    pub int_grid_csv: Vec<i32>,
+    #[serde(rename = "__type")]
+    pub layer_type: String,
+    #[serde(rename = "entityInstances")]
+    pub entity_instances: Vec<EntityInstance>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FieldInstance {
+    #[serde(rename = "__identifier")]
+    pub identifier: String,
+    #[serde(rename = "__value")]
+    pub value: serde_json::Value,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EntityInstance {
+    #[serde(rename = "__grid")]
+    pub grid: Vec<i32>,
+    #[serde(rename = "__identifier")]
+    pub identifier: String,
+    // what EntityRef fields point at
+    #[serde(default)]
+    pub iid: String,
+    #[serde(rename = "fieldInstances")]
+    pub field_instances: Vec<FieldInstance>,
+}
+
+impl EntityInstance {
+    // value of an entity field, None when the field is missing or left empty
+    pub fn field(&self, identifier: &str) -> Option<&serde_json::Value> {
+        self.field_instances
+            .iter()
+            .find(|field| field.identifier == identifier)
+            .map(|field| &field.value)
+            .filter(|value| !value.is_null())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Neighbour {
+    #[serde(rename = "levelIid")]
+    pub level_iid: String,
+}
+
+#[derive(Deserialize, TypeUuid, Clone)]
+#[uuid = "6f3c2a8e-9d1b-4c57-a0e4-3b8f5d2c7e91"]
+pub struct Level {
+    pub identifier: String,
+    pub iid: String,
+    // position in the LDtk world, in pixels
+    #[serde(rename = "worldX")]
+    pub world_x: i32,
+    #[serde(rename = "worldY")]
+    pub world_y: i32,
+    #[serde(rename = "__bgColor")]
+    pub bg_color: String,
+    #[serde(rename = "fieldInstances")]
+    pub field_instances: Vec<FieldInstance>,
+    #[serde(rename = "layerInstances")]
+    pub layer_instances: Vec<LayerInstance>,
+    #[serde(rename = "__neighbours")]
+    pub neighbours: Vec<Neighbour>,
+}
+
+impl Level {
+    pub fn int_grid(&self) -> Option<&LayerInstance> {
+        self.layer_instances
+            .iter()
+            .find(|layer| layer.layer_type == "IntGrid")
+    }
+
+    // top left tile in world tile coordinates and size in tiles
+    pub fn tile_rect(&self) -> LevelRect {
+        match self.int_grid() {
+            Some(layer) => LevelRect {
+                x: self.world_x / layer.grid_size,
+                y: self.world_y / layer.grid_size,
+                width: layer.c_wid,
+                height: layer.c_hei,
+            },
+            None => LevelRect::default(),
+        }
+    }
+
+    // value of a level field, None when the field is missing or left empty
+    pub fn field(&self, identifier: &str) -> Option<&serde_json::Value> {
+        self.field_instances
+            .iter()
+            .find(|field| field.identifier == identifier)
+            .map(|field| &field.value)
+            .filter(|value| !value.is_null())
+    }
+
+    // entities of one kind from all entity layers
+    pub fn entities<'a>(&'a self, identifier: &'a str) -> impl Iterator<Item = &'a EntityInstance> {
+        self.layer_instances
+            .iter()
+            .filter(|layer| layer.layer_type == "Entities")
+            .flat_map(|layer| &layer.entity_instances)
+            .filter(move |entity| entity.identifier == identifier)
+    }
+
+    // first tile with this IntGrid value, in level local coordinates
+    pub fn find_tile(&self, value: i32) -> Option<(i32, i32)> {
+        self.find_tiles(value).into_iter().next()
+    }
+
+    // every tile with this IntGrid value row by row, in level local coordinates
+    pub fn find_tiles(&self, value: i32) -> Vec<(i32, i32)> {
+        let Some(layer) = self.int_grid() else {
+            return Vec::new();
+        };
+        layer
+            .int_grid_csv
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v == value)
+            .map(|(index, _)| (index as i32 % layer.c_wid, index as i32 / layer.c_wid))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct LevelRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl LevelRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    pub fn union(&self, other: &LevelRect) -> LevelRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        LevelRect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+// LDtk project file (.ldtk), lists the levels of the world
+
+#[derive(TypeUuid)]
+#[uuid = "2b7d4e1a-58c3-4f0e-9a6d-c1e8f3b5a274"]
+pub struct LdtkProject {
+    pub levels: Vec<Handle<Level>>,
+    // editor colours of the IntGrid values, e.g. for level thumbnails
+    pub int_grid_colors: HashMap<i32, Color>,
+}
+
+#[derive(Deserialize)]
+struct ProjectFile {
+    defs: Definitions,
+    levels: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Definitions {
+    layers: Vec<LayerDefinition>,
+}
+
+#[derive(Deserialize)]
+struct LayerDefinition {
+    #[serde(rename = "intGridValues")]
+    int_grid_values: Vec<IntGridValue>,
+}
+
+#[derive(Deserialize)]
+struct IntGridValue {
+    value: i32,
+    color: String,
+}
+
+// LDtk writes colours as "#RRGGBB"
+pub fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#')?;
+    let channel = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+    Some(Color::rgb_u8(
+        channel(0..2)?,
+        channel(2..4)?,
+        channel(4..6)?,
+    ))
+}
+
+// reads every level of a project straight from disk, for tools running without the asset server
+pub fn read_project_levels(path: &Path) -> Result<Vec<Level>, bevy::asset::Error> {
+    let project: ProjectFile = serde_json::from_slice(&fs::read(path)?)?;
+    let project_dir = path.parent().unwrap_or(Path::new(""));
+    project
+        .levels
+        .into_iter()
+        .map(
+            |level| match level.get("externalRelPath").and_then(|path| path.as_str()) {
+                Some(rel_path) => Ok(serde_json::from_slice(&fs::read(
+                    project_dir.join(rel_path),
+                )?)?),
+                None => Ok(serde_json::from_value(level)?),
+            },
+        )
+        .collect()
+}
+
+// Asset loader
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ldtkl"]
+    }
+}
+
+#[derive(Default)]
+pub struct LdtkProjectLoader;
+
+impl AssetLoader for LdtkProjectLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let project: ProjectFile = serde_json::from_slice(bytes)?;
+            let project_dir = load_context.path().parent().unwrap().to_path_buf();
+
+            let mut levels = Vec::new();
+            let mut dependencies = Vec::new();
+            for level in project.levels {
+                match level.get("externalRelPath").and_then(|path| path.as_str()) {
+                    // levels saved next to the project are loaded as their own assets
+                    Some(rel_path) => {
+                        let path = AssetPath::new(project_dir.join(rel_path), None);
+                        levels.push(load_context.get_handle(path.clone()));
+                        dependencies.push(path);
+                    }
+                    None => {
+                        let level: Level = serde_json::from_value(level)?;
+                        let label = level.iid.clone();
+                        levels
+                            .push(load_context.set_labeled_asset(&label, LoadedAsset::new(level)));
+                    }
+                }
+            }
+
+            let int_grid_colors = project
+                .defs
+                .layers
+                .iter()
+                .flat_map(|layer| layer.int_grid_values.iter())
+                .filter_map(|value| Some((value.value, parse_color(&value.color)?)))
+                .collect();
+
+            load_context.set_default_asset(
+                LoadedAsset::new(LdtkProject {
+                    levels,
+                    int_grid_colors,
+                })
+                .with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ldtk"]
+    }
+}
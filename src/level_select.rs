@@ -0,0 +1,376 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    collectible::collectibles,
+    level::{parse_color, LdtkProject, Level},
+    loading::GameAssets,
+    save::SaveGame,
+    score::star_text,
+    world::{SelectedLevel, WorldLevels},
+    AppState, END_TILE,
+};
+
+const COLUMNS: usize = 4;
+const THUMBNAIL_SIZE: f32 = 96.;
+// stick deflection needed before it counts as a direction press
+const STICK_THRESHOLD: f32 = 0.5;
+
+const ENTRY_COLOR: Color = Color::rgb(0.15, 0.15, 0.2);
+const ENTRY_FOCUSED_COLOR: Color = Color::rgb(0.35, 0.35, 0.45);
+const LOCKED_TINT: Color = Color::rgb(0.3, 0.3, 0.3);
+
+// Components
+
+#[derive(Component)]
+struct LevelSelectRoot;
+
+#[derive(Component)]
+struct LevelEntry {
+    index: usize,
+}
+
+// Resources
+
+struct EntryInfo {
+    iid: String,
+    unlocked: bool,
+}
+
+// levels in the order they are listed, with the entry that has focus
+#[derive(Resource, Default)]
+struct LevelSelectState {
+    entries: Vec<EntryInfo>,
+    focused: usize,
+}
+
+// Plugin
+
+pub struct LevelSelectPlugin;
+
+impl Plugin for LevelSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LevelSelectState::default());
+        app.add_system(spawn_level_select.in_schedule(OnEnter(AppState::LevelSelect)));
+        app.add_system(despawn_level_select.in_schedule(OnExit(AppState::LevelSelect)));
+        app.add_systems(
+            (navigate_level_select, update_entry_colors)
+                .chain()
+                .in_set(OnUpdate(AppState::LevelSelect)),
+        );
+        app.add_system(back_to_level_select.in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+// one pixel per tile in the colours the levels have in LDtk
+fn render_thumbnail(level: &Level, project: &LdtkProject) -> Option<Image> {
+    let layer = level.int_grid()?;
+    let background = parse_color(&level.bg_color).unwrap_or(Color::BLACK);
+    let data = layer
+        .int_grid_csv
+        .iter()
+        .flat_map(|value| {
+            let color = project
+                .int_grid_colors
+                .get(value)
+                .copied()
+                .unwrap_or(background);
+            color.as_rgba_u32().to_le_bytes()
+        })
+        .collect();
+    Some(Image::new(
+        Extent3d {
+            width: layer.c_wid as u32,
+            height: layer.c_hei as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    ))
+}
+
+// Levels without an End tile cannot be solved, so they never lock anything.
+// The others open up in order, or when the save unlocked them through a neighbour.
+fn unlocked_levels(levels: &[&Level], save: &SaveGame) -> Vec<bool> {
+    let mut previous_solved = true;
+    levels
+        .iter()
+        .map(|level| {
+            if level.find_tile(END_TILE).is_none() {
+                return true;
+            }
+            let progress = save.data.level(&level.iid);
+            let unlocked = previous_solved || progress.unlocked || progress.solved;
+            previous_solved = progress.solved;
+            unlocked
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_level_select(
+    mut commands: Commands,
+    world_levels: WorldLevels,
+    game_assets: Res<GameAssets>,
+    projects: Res<Assets<LdtkProject>>,
+    save: Res<SaveGame>,
+    selected: Res<SelectedLevel>,
+    mut images: ResMut<Assets<Image>>,
+    mut state: ResMut<LevelSelectState>,
+) {
+    let Some(project) = projects.get(&game_assets.project) else {
+        return;
+    };
+    let mut levels: Vec<&Level> = world_levels.iter().collect();
+    levels.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+    let unlocked = unlocked_levels(&levels, &save);
+
+    state.entries = levels
+        .iter()
+        .zip(&unlocked)
+        .map(|(level, unlocked)| EntryInfo {
+            iid: level.iid.clone(),
+            unlocked: *unlocked,
+        })
+        .collect();
+    // come back to the level that was just played
+    state.focused = selected
+        .0
+        .as_ref()
+        .and_then(|iid| state.entries.iter().position(|entry| &entry.iid == iid))
+        .unwrap_or(0);
+
+    let text_style = |size: f32| TextStyle {
+        font: game_assets.font.clone(),
+        font_size: size,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            LevelSelectRoot,
+        ))
+        .with_children(|root| {
+            root.spawn(TextBundle::from_section("sole", text_style(48.)));
+            root.spawn(NodeBundle {
+                style: Style {
+                    flex_wrap: FlexWrap::Wrap,
+                    justify_content: JustifyContent::Center,
+                    max_size: Size::width(Val::Px((THUMBNAIL_SIZE + 32.) * COLUMNS as f32)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_children(|grid| {
+                for (index, level) in levels.iter().enumerate() {
+                    let progress = save.data.level(&level.iid);
+                    let status = if !unlocked[index] {
+                        "locked".to_string()
+                    } else if let Some(best) = progress.best_clicks {
+                        let mut status = format!("{} best {}", star_text(progress.stars), best);
+                        let total = collectibles(level).len();
+                        if total > 0 {
+                            status += &format!(" found {}/{}", progress.collected.len(), total);
+                        }
+                        status
+                    } else {
+                        String::new()
+                    };
+                    let thumbnail = render_thumbnail(level, project)
+                        .map(|image| images.add(image))
+                        .unwrap_or_default();
+
+                    grid.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(8.)),
+                                padding: UiRect::all(Val::Px(8.)),
+                                ..Default::default()
+                            },
+                            background_color: ENTRY_COLOR.into(),
+                            ..Default::default()
+                        },
+                        LevelEntry { index },
+                    ))
+                    .with_children(|entry| {
+                        entry.spawn(ImageBundle {
+                            style: Style {
+                                size: Size::all(Val::Px(THUMBNAIL_SIZE)),
+                                ..Default::default()
+                            },
+                            image: thumbnail.into(),
+                            background_color: if unlocked[index] {
+                                Color::WHITE.into()
+                            } else {
+                                LOCKED_TINT.into()
+                            },
+                            ..Default::default()
+                        });
+                        entry.spawn(TextBundle::from_section(
+                            level.identifier.replace('_', " "),
+                            text_style(14.),
+                        ));
+                        entry.spawn(TextBundle::from_section(status, text_style(12.)));
+                    });
+                }
+            });
+        });
+}
+
+fn despawn_level_select(mut commands: Commands, q_root: Query<Entity, With<LevelSelectRoot>>) {
+    for entity in &q_root {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// direction pressed this frame on keyboard, d-pad or left stick, as (columns, rows)
+fn pressed_direction(
+    keys: &Input<KeyCode>,
+    gamepads: &Gamepads,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    stick_centered: &mut bool,
+) -> (i32, i32) {
+    let mut direction = (0, 0);
+    if keys.any_just_pressed([KeyCode::Left, KeyCode::A]) {
+        direction.0 -= 1;
+    }
+    if keys.any_just_pressed([KeyCode::Right, KeyCode::D]) {
+        direction.0 += 1;
+    }
+    if keys.any_just_pressed([KeyCode::Up, KeyCode::W]) {
+        direction.1 -= 1;
+    }
+    if keys.any_just_pressed([KeyCode::Down, KeyCode::S]) {
+        direction.1 += 1;
+    }
+
+    let mut stick = Vec2::ZERO;
+    for gamepad in gamepads.iter() {
+        let button = |button_type| buttons.just_pressed(GamepadButton::new(gamepad, button_type));
+        if button(GamepadButtonType::DPadLeft) {
+            direction.0 -= 1;
+        }
+        if button(GamepadButtonType::DPadRight) {
+            direction.0 += 1;
+        }
+        if button(GamepadButtonType::DPadUp) {
+            direction.1 -= 1;
+        }
+        if button(GamepadButtonType::DPadDown) {
+            direction.1 += 1;
+        }
+        let axis = |axis_type| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or_default()
+        };
+        stick += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+
+    // the stick moves once per push, it has to come back to the centre in between
+    if stick.length() < STICK_THRESHOLD {
+        *stick_centered = true;
+    } else if *stick_centered {
+        *stick_centered = false;
+        if stick.x.abs() > stick.y.abs() {
+            direction.0 += stick.x.signum() as i32;
+        } else {
+            direction.1 -= stick.y.signum() as i32;
+        }
+    }
+    direction
+}
+
+#[allow(clippy::too_many_arguments)]
+fn navigate_level_select(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    q_entry: Query<(&Interaction, &LevelEntry), Changed<Interaction>>,
+    mut state: ResMut<LevelSelectState>,
+    mut selected: ResMut<SelectedLevel>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut stick_centered: Local<bool>,
+) {
+    if state.entries.is_empty() {
+        return;
+    }
+
+    let mut confirm = false;
+    for (interaction, entry) in &q_entry {
+        match interaction {
+            Interaction::Hovered => state.focused = entry.index,
+            Interaction::Clicked => {
+                state.focused = entry.index;
+                confirm = true;
+            }
+            Interaction::None => (),
+        }
+    }
+
+    let (columns, rows) = pressed_direction(&keys, &gamepads, &buttons, &axes, &mut stick_centered);
+    let last = state.entries.len() as i32 - 1;
+    let focused = state.focused as i32 + columns + rows * COLUMNS as i32;
+    state.focused = focused.clamp(0, last) as usize;
+
+    confirm |= keys.any_just_pressed([KeyCode::Return, KeyCode::Space]);
+    confirm |= gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South)));
+
+    let entry = &state.entries[state.focused];
+    if confirm && entry.unlocked {
+        selected.0 = Some(entry.iid.clone());
+        next_state.set(AppState::InGame);
+    }
+}
+
+fn update_entry_colors(
+    state: Res<LevelSelectState>,
+    mut q_entry: Query<(&LevelEntry, &mut BackgroundColor)>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+    for (entry, mut color) in &mut q_entry {
+        *color = if entry.index == state.focused {
+            ENTRY_FOCUSED_COLOR.into()
+        } else {
+            ENTRY_COLOR.into()
+        };
+    }
+}
+
+fn back_to_level_select(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let back = keys.just_pressed(KeyCode::Escape)
+        || gamepads.iter().any(|gamepad| {
+            buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Select))
+        });
+    if back {
+        next_state.set(AppState::LevelSelect);
+    }
+}
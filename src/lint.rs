@@ -0,0 +1,212 @@
+use bevy::asset::FileAssetIo;
+
+use crate::{
+    caster::Rail,
+    caster_dir,
+    hazards::{asteroids, gravity_wells, BLACK_HOLE_TILE},
+    level::{read_project_levels, Level},
+    loading::PROJECT_PATH,
+    solver::solve,
+    world::start_positions,
+    END_TILE,
+};
+
+// most clicks a level with hazards may need to pass the lint
+const MAX_LINT_CLICKS: usize = 3;
+
+// Authoring mistakes LDtk cannot catch, found by `--lint`
+#[derive(Debug, PartialEq)]
+pub enum Lint {
+    // a rail of `entity` runs over a tile that is not empty or leaves the level
+    RailBlocked {
+        entity: &'static str,
+        x: i32,
+        y: i32,
+    },
+    // a gravity well without strength or reach does nothing
+    IdleWell {
+        x: i32,
+        y: i32,
+    },
+    // the ship would be lost on the tick it starts
+    StartInHazard {
+        x: i32,
+        y: i32,
+    },
+    // a Trigger off an empty tile, a Door off an obstacle or a Caster_remote
+    // off a caster
+    Misplaced {
+        entity: &'static str,
+        x: i32,
+        y: i32,
+    },
+    // a Trigger that acts on nothing
+    IdleTrigger {
+        x: i32,
+        y: i32,
+    },
+    // no way past the level's hazards to the goal within this many clicks
+    Unsolvable {
+        clicks: usize,
+    },
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lint::RailBlocked { entity, x, y } => {
+                write!(
+                    f,
+                    "{} rail runs over {}, {} which is not empty",
+                    entity, x, y
+                )
+            }
+            Lint::IdleWell { x, y } => {
+                write!(f, "Gravity_well at {}, {} has no strength or radius", x, y)
+            }
+            Lint::StartInHazard { x, y } => {
+                write!(f, "the ship starts in a hazard at {}, {}", x, y)
+            }
+            Lint::Misplaced { entity, x, y } => {
+                let wanted = match *entity {
+                    "Trigger" => "an empty tile",
+                    "Door" => "an obstacle",
+                    _ => "a caster",
+                };
+                write!(f, "{} at {}, {} is not on {}", entity, x, y, wanted)
+            }
+            Lint::IdleTrigger { x, y } => {
+                write!(f, "Trigger at {}, {} has no targets", x, y)
+            }
+            Lint::Unsolvable { clicks } => {
+                write!(f, "no solution within {} clicks avoids the hazards", clicks)
+            }
+        }
+    }
+}
+
+// everything wrong with a level, tiles are level local. `levels` are the
+// ones the level may stream in.
+pub fn lint_level(level: &Level, levels: &[Level]) -> Vec<Lint> {
+    let rect = level.tile_rect();
+    let tile = |x: i32, y: i32| -> Option<i32> {
+        let layer = level.int_grid()?;
+        let (x, y) = (x - rect.x, y - rect.y);
+        let inside = (0..layer.c_wid).contains(&x) && (0..layer.c_hei).contains(&y);
+        inside.then(|| layer.int_grid_csv[(x + layer.c_wid * y) as usize])
+    };
+    let mut lints = Vec::new();
+
+    // moving casters start on their own IntGrid tile, asteroids on an empty one
+    let asteroid_rails: Vec<Rail> = asteroids(level)
+        .into_iter()
+        .map(|asteroid| asteroid.rail)
+        .collect();
+    let caster_rails = level
+        .entities("Caster_motion")
+        .map(|entity| ("Caster_motion", Rail::from_entity(entity, rect), 1));
+    let rails = caster_rails.chain(
+        asteroid_rails
+            .iter()
+            .map(|rail| ("Asteroid", rail.clone(), 0)),
+    );
+    for (entity, rail, skip) in rails {
+        for (x, y) in rail.tiles.iter().skip(skip) {
+            if tile(*x, *y) != Some(0) {
+                lints.push(Lint::RailBlocked {
+                    entity,
+                    x: x - rect.x,
+                    y: y - rect.y,
+                });
+            }
+        }
+    }
+
+    for well in gravity_wells(level) {
+        if well.strength <= 0. || well.radius < 1 {
+            lints.push(Lint::IdleWell {
+                x: well.x - rect.x,
+                y: well.y - rect.y,
+            });
+        }
+    }
+
+    // the entities of triggers.rs and the tiles they belong on
+    let placements = ["Trigger", "Door", "Caster_remote"];
+    for identifier in placements {
+        for entity in level.entities(identifier) {
+            let (x, y) = (entity.grid[0], entity.grid[1]);
+            let value = tile(rect.x + x, rect.y + y);
+            let fits = match identifier {
+                "Trigger" => value == Some(0),
+                "Door" => value == Some(1),
+                _ => value.and_then(caster_dir).is_some(),
+            };
+            if !fits {
+                lints.push(Lint::Misplaced {
+                    entity: identifier,
+                    x,
+                    y,
+                });
+            }
+            let targets = entity.field("targets").and_then(|value| value.as_array());
+            if identifier == "Trigger" && targets.is_none_or(|targets| targets.is_empty()) {
+                lints.push(Lint::IdleTrigger { x, y });
+            }
+        }
+    }
+
+    for (x, y) in start_positions(level) {
+        let (x, y) = (x as i32, y as i32);
+        let on_rail = asteroid_rails
+            .iter()
+            .any(|rail| rail.tiles.contains(&(x, y)));
+        if tile(x, y) == Some(BLACK_HOLE_TILE) || on_rail {
+            lints.push(Lint::StartInHazard {
+                x: x - rect.x,
+                y: y - rect.y,
+            });
+        }
+    }
+
+    // hazards can make a goal unreachable, see solver.rs. Only worth the
+    // search once nothing else is wrong.
+    let hazards = !level.find_tiles(BLACK_HOLE_TILE).is_empty()
+        || !asteroid_rails.is_empty()
+        || level.entities("Gravity_well").next().is_some();
+    let goal = !level.find_tiles(END_TILE).is_empty();
+    if lints.is_empty() && hazards && goal && solve(level, levels, MAX_LINT_CLICKS).is_none() {
+        lints.push(Lint::Unsolvable {
+            clicks: MAX_LINT_CLICKS,
+        });
+    }
+    lints
+}
+
+// `--lint`, prints what is wrong with each level of the project and returns
+// the exit code
+pub fn lint_command() -> i32 {
+    let project = FileAssetIo::get_base_path()
+        .join("assets")
+        .join(PROJECT_PATH);
+    let levels = match read_project_levels(&project) {
+        Ok(levels) => levels,
+        Err(error) => {
+            eprintln!("could not read '{}': {}", project.display(), error);
+            return 2;
+        }
+    };
+    let mut found = 0;
+    for level in &levels {
+        for lint in lint_level(level, &levels) {
+            println!("{}: {}", level.identifier, lint);
+            found += 1;
+        }
+    }
+    if found > 0 {
+        1
+    } else {
+        println!("{} levels, nothing to report", levels.len());
+        0
+    }
+}
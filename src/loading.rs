@@ -0,0 +1,107 @@
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::{level::LdtkProject, AppState};
+
+pub const PROJECT_PATH: &str = "level.ldtk";
+
+// Resources
+
+// every handle gameplay needs, filled while in AppState::Loading
+#[derive(Resource, Default)]
+pub struct GameAssets {
+    pub tile: Handle<Image>,
+    // the ship with its sail deployed and without
+    pub ship: Handle<Image>,
+    pub ship_hull: Handle<Image>,
+    pub light_ray: Handle<Image>,
+    pub light_ray_atlas: Handle<TextureAtlas>,
+    pub font: Handle<Font>,
+    pub project: Handle<LdtkProject>,
+}
+
+impl GameAssets {
+    // the project's levels only become known once the project itself is loaded
+    fn untyped_handles(&self, projects: &Assets<LdtkProject>) -> Vec<HandleUntyped> {
+        let mut handles = vec![
+            self.tile.clone_untyped(),
+            self.ship.clone_untyped(),
+            self.ship_hull.clone_untyped(),
+            self.light_ray.clone_untyped(),
+            self.font.clone_untyped(),
+            self.project.clone_untyped(),
+        ];
+        if let Some(project) = projects.get(&self.project) {
+            handles.extend(project.levels.iter().map(|level| level.clone_untyped()));
+        }
+        handles
+    }
+}
+
+// Plugin
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(start_loading.in_schedule(OnEnter(AppState::Loading)));
+        app.add_system(check_loading.in_set(OnUpdate(AppState::Loading)));
+    }
+}
+
+fn start_loading(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let light_ray = assets.load("photon_ray_6spd_white_40alpha.png");
+    let light_ray_atlas = texture_atlases.add(TextureAtlas::from_grid(
+        light_ray.clone(),
+        Vec2::new(32.0, 32.0),
+        6,
+        1,
+        None,
+        None,
+    ));
+
+    commands.insert_resource(GameAssets {
+        tile: assets.load("tiles_middle.png"),
+        ship: assets.load("high_res_spacecrafts/true_pixel_art_spaceship_solarsail.png"),
+        ship_hull: assets.load("high_res_spacecrafts/spaceship_orange_pixelart.png"),
+        light_ray,
+        light_ray_atlas,
+        font: assets.load("fonts/DejaVuSansMono.ttf"),
+        project: assets.load(PROJECT_PATH),
+    });
+}
+
+fn check_loading(
+    game_assets: Res<GameAssets>,
+    assets: Res<AssetServer>,
+    projects: Res<Assets<LdtkProject>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut reported: Local<bool>,
+) {
+    let handles = game_assets.untyped_handles(&projects);
+    match assets.get_group_load_state(handles.iter().map(|handle| handle.id())) {
+        LoadState::Loaded if projects.contains(&game_assets.project) => {
+            next_state.set(AppState::LevelSelect)
+        }
+        LoadState::Failed => {
+            // stay in the loading state, but say exactly what is missing once
+            if *reported {
+                return;
+            }
+            *reported = true;
+            for handle in &handles {
+                if assets.get_load_state(handle) != LoadState::Failed {
+                    continue;
+                }
+                match assets.get_handle_path(handle) {
+                    Some(path) => error!("failed to load asset 'assets/{}'", path.path().display()),
+                    None => error!("failed to load an asset without a path"),
+                }
+            }
+        }
+        _ => (),
+    }
+}
@@ -1,68 +1,90 @@
-use std::{default, string};
+use std::{
+    collections::HashSet,
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
 
 use bevy::{
-    input::mouse::MouseButtonInput,
-    pbr::{MaterialPipeline, MaterialPipelineKey},
     prelude::*,
-    reflect::TypeUuid,
-    render::{
-        mesh::MeshVertexBufferLayout,
-        render_resource::{
-            AsBindGroup, FilterMode, RenderPipelineDescriptor, ShaderRef,
-            SpecializedMeshPipelineError,
-        },
-    },
-    sprite::{Anchor, MaterialMesh2dBundle, Sprite, SpriteBundle},
+    sprite::{Anchor, Sprite, SpriteBundle},
     DefaultPlugins,
 };
-use picking::{PickCamera, PickState, Pickable, PickingPlugin, Triangle};
-use serde::Deserialize;
-
+use camera::GameCameraPlugin;
+use caster::{caster_motions, click_caster, Beam, CasterPlugin, MovingCaster};
+use collectible::{collected_here, spawn_collectibles, CollectiblePlugin};
+use fleet::{step_fleet, FleetMember, FleetPlugin};
+use generator::generate_command;
+use hazards::{asteroids, spawn_hazards, GravityWell, HazardPlugin, ASTEROID_TILE};
+use level::{LdtkProject, LdtkProjectLoader, Level, LevelLoader, LevelRect};
+use level_select::LevelSelectPlugin;
+use lint::lint_command;
+use loading::{GameAssets, LoadingPlugin};
+use physics::ShipMode;
+use picking::{PickLayers, PickState, Pickable, PickingPlugin, Triangle};
+use preview::PreviewPlugin;
+use priority::{ray_z, RayPriority};
+use pulse::{caster_timings, fade, BeamTiming};
+use replay::{verify_command, ReplayPlugin};
+use save::SavePlugin;
+use score::ScorePlugin;
+use ship::{spawn_sail, ShipPlugin, ShipPose};
+use triggers::{
+    door_tiles, spawn_triggers, trigger_tiles, TriggerNetwork, TriggerPlugin, TRIGGER_TILE,
+};
+use world::{CurrentLevel, LevelEntity, WorldStreamingPlugin};
+
+mod camera;
+mod caster;
+mod collectible;
+mod fleet;
+mod generator;
+mod hazards;
+mod level;
+mod level_select;
+mod lint;
+mod loading;
+mod movement;
+mod physics;
 mod picking;
+mod preview;
+mod priority;
+mod pulse;
+mod replay;
+mod save;
+mod score;
+mod ship;
+mod solver;
+#[cfg(test)]
+mod tests;
+mod triggers;
+mod world;
 
 const TILE_SIZE: f32 = 32.0;
-const LEVEL_SIZE_X: f32 = 16.0;
-const LEVEL_SIZE_Y: f32 = 16.0;
 const PLAYER_SPEED: f32 = 2.;
+const END_TILE: i32 = 7;
 
-static mut light_ray_texture_handle: Option<Handle<TextureAtlas>> = None; 
+// length of one simulation tick, the ship only ever moves in whole ticks so
+// predictions and replays match what happens in game exactly
+const SIM_STEP: f32 = 1. / 60.;
+// spacing of the dots when simulating the ship ahead of time
+const PREDICTION_SPACING: f32 = 0.5;
+
+const PICK_LAYER_CASTERS: u8 = 0;
 
 const RAY_COLORS: [Color; 4] = [
-    Color::rgb(255. / 255., 206. / 255., 92. / 255.),
+    Color::rgb(1., 206. / 255., 92. / 255.),
     Color::rgb(235. / 255., 171. / 255., 52. / 255.),
     Color::rgb(165. / 255., 224. / 255., 47. / 255.),
     Color::rgb(69. / 255., 97. / 255., 237. / 255.),
 ];
 
-#[derive(Deserialize)]
-struct LayerInstance {
-    __cHei: i32,
-    __cWid: i32,
-    intGridCsv: Vec<i32>,
-    __type: String,
-    entityInstances: Vec<EntityInstance>,
-}
-
-#[derive(Deserialize)]
-struct FieldInstance {
-    __identifier: String,
-    __type: String,
-    __value: serde_json::Value,
-}
-
-#[derive(Deserialize)]
-struct EntityInstance {
-    __grid: Vec<i32>,
-    __identifier: String,
-    fieldInstances: Vec<FieldInstance>,
-}
-
-#[derive(Deserialize)]
-struct Level {
-    layerInstances: Vec<LayerInstance>,
+// the whole ticks closest to an authored number of seconds, 0 for 0 s and
+// below. Callers decide what a timer of 0 ticks means.
+fn seconds_to_ticks(seconds: f64) -> u32 {
+    (seconds as f32 / SIM_STEP).round() as u32
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, PartialEq)]
 struct RayCaster {
     dir: Dir,
     pos_x: i32,
@@ -78,7 +100,7 @@ struct AnimationIndex {
 #[derive(Component, Deref, DerefMut)]
 struct AnimationTimer(Timer);
 
-#[derive(Component)]
+#[derive(Component, Clone, PartialEq)]
 struct Ray {
     src_x: i32,
     src_y: i32,
@@ -86,13 +108,52 @@ struct Ray {
     dest_y: i32,
     reversed: bool,
     horizontal: bool,
-    // smaller prio value means its above rays with higher value
+    // smaller prio value means its above rays with higher value, see RayPriority
     prio: i32,
+    timing: BeamTiming,
+    // the first tick the ray exists on
+    born: u32,
+    // how hard the ray pushes ships in ShipMode::Sail
+    intensity: f32,
+    // dark while a trigger switches its caster off, see triggers.rs
+    switched_off: bool,
 }
 
 #[derive(Resource, Default, Clone)]
 struct GameState {
     ray_count: i32,
+    // casters the player activated since entering the current level
+    activations: i32,
+    // simulation ticks since entering the current level
+    tick: u32,
+    // world tiles of the collectibles picked up since entering the current level
+    collected: Vec<(i32, i32)>,
+    // fleet indices of the ships that reached their goal in the current level
+    arrived: Vec<usize>,
+    // levels already reported as solved in this run
+    solved: HashSet<String>,
+}
+
+// sent once when the last ship of the fleet reaches its goal in a level
+struct LevelSolved {
+    iid: String,
+    clicks: i32,
+    // seconds it took from entering the level
+    time: f32,
+    // level local tiles of the collectibles picked up on the way
+    collected: Vec<(i32, i32)>,
+}
+
+// sent every tick the ship is in a hazard, the level restarts
+struct ShipLost;
+
+// sent when a caster fires, by the player or a replay
+struct CasterActivated {
+    // the caster in world tile coordinates
+    x: i32,
+    y: i32,
+    // the ray exists from this simulation tick on
+    tick: u32,
 }
 
 #[derive(Eq, PartialEq, Clone)]
@@ -103,145 +164,301 @@ enum Dir {
     Rightwards,
 }
 
-#[derive(Component)]
+impl Dir {
+    // one tile in grid coordinates, y grows downwards
+    fn step(&self) -> (i32, i32) {
+        match self {
+            Dir::Upwards => (0, -1),
+            Dir::Downwards => (0, 1),
+            Dir::Leftwards => (-1, 0),
+            Dir::Rightwards => (1, 0),
+        }
+    }
+
+    // the direction after `quarters` quarter turns clockwise
+    fn turned(&self, quarters: u32) -> Dir {
+        const CLOCKWISE: [Dir; 4] = [
+            Dir::Upwards,
+            Dir::Rightwards,
+            Dir::Downwards,
+            Dir::Leftwards,
+        ];
+        let index = CLOCKWISE
+            .iter()
+            .position(|dir| dir == self)
+            .unwrap_or_default();
+        CLOCKWISE[(index + quarters as usize) % 4].clone()
+    }
+
+    // counter clockwise from Rightwards, as sprites are rotated
+    fn angle(&self) -> f32 {
+        match self {
+            Dir::Upwards => PI / 2.,
+            Dir::Downwards => -PI / 2.,
+            Dir::Leftwards => PI,
+            Dir::Rightwards => 0.,
+        }
+    }
+}
+
+// IntGrid values of the streamed in levels in world tile coordinates, row by
+// row, with 0 in the space between levels
+#[derive(Resource, Default, Clone)]
+struct LevelGrid {
+    rect: LevelRect,
+    tiles: Vec<i32>,
+}
+
+impl LevelGrid {
+    fn new<'a>(levels: impl IntoIterator<Item = &'a Level>) -> Self {
+        let levels: Vec<&Level> = levels.into_iter().collect();
+        let Some(rect) = levels
+            .iter()
+            .map(|level| level.tile_rect())
+            .reduce(|a, b| a.union(&b))
+        else {
+            return LevelGrid::default();
+        };
+
+        let mut grid = LevelGrid {
+            rect,
+            tiles: vec![0; (rect.width * rect.height) as usize],
+        };
+        for level in &levels {
+            let Some(layer) = level.int_grid() else {
+                continue;
+            };
+            let level_rect = level.tile_rect();
+            for (index, value) in layer.int_grid_csv.iter().enumerate() {
+                let x = level_rect.x + index as i32 % layer.c_wid;
+                let y = level_rect.y + index as i32 / layer.c_wid;
+                let grid_index = grid.index(x, y);
+                grid.tiles[grid_index] = *value;
+            }
+        }
+        // asteroids on the tile they start on, see hazards.rs
+        for asteroid in levels.iter().flat_map(|level| asteroids(level)) {
+            let (x, y) = asteroid.tile;
+            grid.set(x, y, ASTEROID_TILE);
+        }
+        for (x, y) in levels.iter().flat_map(|level| trigger_tiles(level)) {
+            grid.set(x, y, TRIGGER_TILE);
+        }
+        grid
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        self.rect.contains(x, y)
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        ((x - self.rect.x) + self.rect.width * (y - self.rect.y)) as usize
+    }
+
+    fn get(&self, x: i32, y: i32) -> i32 {
+        if !self.contains(x, y) {
+            return 0;
+        }
+        self.tiles[self.index(x, y)]
+    }
+
+    fn set(&mut self, x: i32, y: i32, value: i32) {
+        if self.contains(x, y) {
+            let index = self.index(x, y);
+            self.tiles[index] = value;
+        }
+    }
+
+    // obstacles, casters, asteroids and triggers stop light
+    fn blocks_beam(&self, x: i32, y: i32) -> bool {
+        matches!(self.get(x, y), 1..=5 | ASTEROID_TILE | TRIGGER_TILE)
+    }
+}
+
+#[derive(Component, Clone)]
 struct Player {
     x: f32,
     y: f32,
     direction: Option<Dir>,
-    last_direction: Option<Dir>,
-}
-
-fn setup_textures(assets: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlas>>) {
-    let light_ray_texture_atlas = TextureAtlas::from_grid(
-        // assets.load("lightray.png"),
-        assets.load("photon_ray_6spd_white_40alpha.png"),
-        Vec2::new(32.0, 32.0),
-        6,
-        1,
-        None,
-        None,
-    );
-    unsafe {
-        light_ray_texture_handle = Some(texture_atlases.add(light_ray_texture_atlas));
-    }
+    // tiles per second, only kept in levels with ShipMode::Sail
+    velocity: Vec2,
 }
 
-fn render_map(
-    mut commands: Commands,
-    assets: Res<AssetServer>,
-    mut game_state: ResMut<GameState>,
+// spawns tiles, casters and authored rays of a level at its world position,
+// everything tagged with LevelEntity so it can be streamed out again
+fn spawn_level(
+    level: &Level,
+    game_assets: &GameAssets,
+    priority: &RayPriority,
+    game_state: &mut GameState,
+    commands: &mut Commands,
 ) {
-    let level_raw = include_str!("../assets/level/Level_3.ldtkl");
-    let level: Level = serde_json::from_str(level_raw).unwrap();
-    for layer in level.layerInstances {
-        match layer.__type.as_str() {
-            "IntGrid" => {
-                let pickable = Pickable {
-                    triangles: vec![
-                        Triangle::new(
-                            Vec2::new(-16., 16.),
-                            Vec2::new(16., 16.),
-                            Vec2::new(-16., -16.),
-                        ),
-                        Triangle::new(
-                            Vec2::new(16., 16.),
-                            Vec2::new(-16., -16.),
-                            Vec2::new(16., -16.),
-                        ),
-                    ],
-                };
-                for x in 0..layer.__cWid {
-                    for y in 0..layer.__cHei {
-                        let index: usize = (x + layer.__cWid * y) as usize;
-                        let value = layer.intGridCsv[index];
-                        match value {
-                            1 => {
-                                commands.spawn(SpriteBundle {
-                                    texture: assets.load("tiles_middle.png"),
-                                    transform: Transform::from_xyz(
-                                        (x as f32) * TILE_SIZE,
-                                        -(y as f32) * TILE_SIZE,
-                                        100.,
-                                    ),
-                                    ..Default::default()
-                                });
-                            }
-                            2..=5 => {
-                                let dir: Dir = match value {
-                                    2 => Dir::Downwards,
-                                    3 => Dir::Upwards,
-                                    4 => Dir::Rightwards,
-                                    5 => Dir::Leftwards,
-                                    // wtf are we doing?
-                                    _ => Dir::Leftwards,
-                                };
-                                // pickable tiles
-                                commands.spawn((
-                                    SpriteBundle {
-                                        texture: assets.load("tiles_middle.png"),
-                                        transform: Transform::from_xyz(
-                                            (x as f32) * TILE_SIZE,
-                                            -(y as f32) * TILE_SIZE,
-                                            100.,
-                                        ),
-                                        ..Default::default()
-                                    },
-                                    pickable.clone(),
-                                    RayCaster {
-                                        dir,
-                                        pos_x: x,
-                                        pos_y: y,
-                                    },
-                                ));
-                            }
-                            _ => (),
+    let rect = level.tile_rect();
+    let level_entity = LevelEntity(level.iid.clone());
+    let motions = caster_motions(level);
+    let timings = caster_timings(level);
+    // doors have their own sprite, see triggers.rs
+    let doors = door_tiles(level);
+    if let Some(layer) = level.int_grid() {
+        let pickable = Pickable::new(vec![
+            Triangle::new(
+                Vec2::new(-16., 16.),
+                Vec2::new(16., 16.),
+                Vec2::new(-16., -16.),
+            ),
+            Triangle::new(
+                Vec2::new(16., 16.),
+                Vec2::new(-16., -16.),
+                Vec2::new(16., -16.),
+            ),
+        ])
+        .with_layers(PickLayers::layer(PICK_LAYER_CASTERS));
+        for x in 0..layer.c_wid {
+            for y in 0..layer.c_hei {
+                let index: usize = (x + layer.c_wid * y) as usize;
+                let value = layer.int_grid_csv[index];
+                let (x, y) = (rect.x + x, rect.y + y);
+                match value {
+                    1 if !doors.contains(&(x, y)) => {
+                        commands.spawn((
+                            SpriteBundle {
+                                texture: game_assets.tile.clone(),
+                                transform: Transform::from_xyz(
+                                    (x as f32) * TILE_SIZE,
+                                    -(y as f32) * TILE_SIZE,
+                                    100.,
+                                ),
+                                ..Default::default()
+                            },
+                            level_entity.clone(),
+                        ));
+                    }
+                    _ => {
+                        let Some(dir) = caster_dir(value) else {
+                            continue;
+                        };
+                        // pickable tiles
+                        let mut caster = commands.spawn((
+                            SpriteBundle {
+                                texture: game_assets.tile.clone(),
+                                transform: Transform::from_xyz(
+                                    (x as f32) * TILE_SIZE,
+                                    -(y as f32) * TILE_SIZE,
+                                    100.,
+                                ),
+                                ..Default::default()
+                            },
+                            pickable.clone(),
+                            RayCaster {
+                                dir: dir.clone(),
+                                pos_x: x,
+                                pos_y: y,
+                            },
+                            level_entity.clone(),
+                        ));
+                        if let Some(motion) = motions.get(&(x, y)) {
+                            caster.insert(MovingCaster::new(motion.clone(), dir));
+                        }
+                        if let Some(timing) = timings.get(&(x, y)) {
+                            caster.insert(timing.clone());
                         }
                     }
                 }
             }
-            "Entities" => {
-                for entity in layer.entityInstances {
-                    match entity.__identifier.as_str() {
-                        "Lightray" => {
-                            let mut dest_x: i32 = 0;
-                            let mut dest_y: i32 = 0;
-                            let mut src_x = entity.__grid[0];
-                            let mut src_y = entity.__grid[1];
-                            let mut prio = 0;
-                            for field in entity.fieldInstances {
-                                match field.__identifier.as_str() {
-                                    "destination" => {
-                                        let obj = field.__value.as_object().unwrap();
-                                        dest_x = obj.get("cx").unwrap().as_i64().unwrap() as i32;
-                                        dest_y = obj.get("cy").unwrap().as_i64().unwrap() as i32;
-                                    }
-                                    "priority" => {
-                                        prio = field.__value.as_i64().unwrap() as i32;
-                                    }
-                                    _ => (),
-                                }
-                            }
-                            spawn_ray(src_x, src_y, dest_x, dest_y, prio, &mut commands);
-                            game_state.ray_count += 1;
+        }
+    }
+    spawn_hazards(level, game_assets, &level_entity, commands);
+    spawn_triggers(level, game_assets, &level_entity, commands);
+    spawn_collectibles(level, game_assets, &level_entity, commands);
+    for ray in level_rays(level, priority, game_state.ray_count) {
+        let ray = spawn_ray(ray, game_assets, commands);
+        commands.entity(ray).insert(level_entity.clone());
+        game_state.ray_count += 1;
+    }
+}
+
+// IntGrid values 2 to 5 are casters shooting in these directions
+fn caster_dir(value: i32) -> Option<Dir> {
+    match value {
+        2 => Some(Dir::Downwards),
+        3 => Some(Dir::Upwards),
+        4 => Some(Dir::Rightwards),
+        5 => Some(Dir::Leftwards),
+        _ => None,
+    }
+}
+
+// the IntGrid value of a caster shooting in `dir`
+fn caster_value(dir: &Dir) -> i32 {
+    match dir {
+        Dir::Downwards => 2,
+        Dir::Upwards => 3,
+        Dir::Rightwards => 4,
+        Dir::Leftwards => 5,
+    }
+}
+
+// rays placed in LDtk as Lightray entities, in world tile coordinates,
+// with prios as if spawned after `count` other rays
+fn level_rays(level: &Level, priority: &RayPriority, count: i32) -> Vec<Ray> {
+    let rect = level.tile_rect();
+    let mut rays = Vec::new();
+    for layer in &level.layer_instances {
+        if layer.layer_type != "Entities" {
+            continue;
+        }
+        for entity in &layer.entity_instances {
+            if entity.identifier == "Lightray" {
+                let mut dest_x: i32 = 0;
+                let mut dest_y: i32 = 0;
+                let src_x = rect.x + entity.grid[0];
+                let src_y = rect.y + entity.grid[1];
+                let mut prio = 0;
+                for field in &entity.field_instances {
+                    match field.identifier.as_str() {
+                        "destination" => {
+                            let obj = field.value.as_object().unwrap();
+                            dest_x = rect.x + obj.get("cx").unwrap().as_i64().unwrap() as i32;
+                            dest_y = rect.y + obj.get("cy").unwrap().as_i64().unwrap() as i32;
+                        }
+                        "priority" => {
+                            prio = field.value.as_i64().unwrap() as i32;
                         }
                         _ => (),
                     }
                 }
+                let prio = priority.authored(&level.iid, prio, count + rays.len() as i32);
+                let timing = BeamTiming::from_entity(entity);
+                let mut ray = Ray::new(src_x, src_y, dest_x, dest_y, prio).timed(timing, 0);
+                if let Some(intensity) = entity.field("intensity").and_then(|value| value.as_f64())
+                {
+                    ray.intensity = intensity as f32;
+                }
+                rays.push(ray);
             }
-            _ => (),
         }
     }
+    rays
 }
 
+// photons of rays fade out while their ray is not lit
 fn update_animations(
     time: Res<Time>,
+    game_state: Res<GameState>,
     mut query: Query<(
         &AnimationIndex,
         &mut AnimationTimer,
         &mut TextureAtlasSprite,
+        Option<&Parent>,
     )>,
+    q_ray: Query<&Ray>,
 ) {
-    for (index, mut timer, mut sprite) in &mut query {
+    for (index, mut timer, mut sprite, parent) in &mut query {
+        if let Some(ray) = parent.and_then(|parent| q_ray.get(parent.get()).ok()) {
+            let alpha = sprite.color.a();
+            let lit = ray.lit(game_state.tick);
+            sprite.color.set_a(fade(alpha, lit, time.delta_seconds()));
+        }
         timer.tick(time.delta());
         if timer.just_finished() {
             sprite.index = if sprite.index == index.last {
@@ -253,193 +470,218 @@ fn update_animations(
     }
 }
 
-fn setup_player(mut commands: Commands, assets: Res<AssetServer>) {
-    commands.spawn((
-        SpriteBundle {
-            texture: assets.load("high_res_spacecrafts/true_pixel_art_spaceship_solarsail.png"),
-            sprite: Sprite {
-                anchor: Anchor::Center,
+// the ship's hull with its sail as a child, see ship.rs
+fn spawn_player(x: f32, y: f32, game_assets: &GameAssets, commands: &mut Commands) -> Entity {
+    let sail = spawn_sail(game_assets, commands);
+    commands
+        .spawn((
+            SpriteBundle {
+                texture: game_assets.ship_hull.clone(),
+                sprite: Sprite {
+                    anchor: Anchor::Center,
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0., -100., 200.),
                 ..Default::default()
             },
-            transform: Transform::from_xyz(0., -100., 200.),
-            ..Default::default()
-        },
-        Player {
-            x: 3.,
-            y: 10.,
-            direction: None,
-            last_direction: None,
-        },
-    ));
-}
-
-fn setup_camera(mut commands: Commands) {
-    commands.spawn((
-        Camera2dBundle {
-            transform: Transform::from_xyz(
-                LEVEL_SIZE_X / 2. * TILE_SIZE,
-                -LEVEL_SIZE_Y / 2. * TILE_SIZE,
-                1000.,
-            ),
-            ..Default::default()
-        },
-        PickCamera,
-    ));
+            Player {
+                x,
+                y,
+                direction: None,
+                velocity: Vec2::ZERO,
+            },
+            ShipPose::default(),
+        ))
+        .push_children(&[sail])
+        .id()
 }
 
 fn move_player(
-    time: Res<Time>,
-    mut q_player: Query<(&mut Player, &mut Transform)>,
+    game_state: Res<GameState>,
+    current: Res<CurrentLevel>,
+    grid: Res<LevelGrid>,
+    mut q_player: Query<(&mut Player, &FleetMember, &mut Transform)>,
     q_ray: Query<&Ray>,
+    q_well: Query<&GravityWell>,
 ) {
-    for (mut player, mut transform) in &mut q_player {
-        let mut heighest_prio = 999999;
-        let mut x_diff = 0.;
-        let mut y_diff = 0.;
-
-        let player_x = player.x as i32;
-        let player_y = player.y as i32;
-        let mut new_direction: Option<Dir> = None;
-        for ray in &q_ray {
-            let mut y_offset = 0;
-            let mut x_offset = 0;
-
-            if (matches!(player.direction, Some(Dir::Upwards))
-                || (matches!(player.direction, Some(Dir::Rightwards))
-                    && matches!(player.last_direction, Some(Dir::Upwards))))
-                && ray.reversed
-                && ray.horizontal
-            {
-                y_offset = -1;
-            }
-            if (matches!(player.direction, Some(Dir::Upwards))
-                || (matches!(player.direction, Some(Dir::Leftwards))
-                    && matches!(player.last_direction, Some(Dir::Upwards))))
-                && !ray.reversed
-                && ray.horizontal
-            {
-                y_offset = -1;
-            }
-            if (matches!(player.direction, Some(Dir::Leftwards))
-                || (matches!(player.direction, Some(Dir::Downwards))
-                    && matches!(player.last_direction, Some(Dir::Leftwards))))
-                && !ray.reversed
-                && !ray.horizontal
-            {
-                x_offset = -1;
-            }
-            if (matches!(player.direction, Some(Dir::Leftwards))
-                || (matches!(player.direction, Some(Dir::Upwards))
-                    && matches!(player.last_direction, Some(Dir::Leftwards))))
-                && ray.reversed
-                && !ray.horizontal
-            {
-                x_offset = -1;
-            }
+    let wells: Vec<GravityWell> = q_well.iter().cloned().collect();
+    let mut ships: Vec<_> = q_player.iter_mut().collect();
+    ships.sort_by_key(|(_, member, _)| member.index);
+    let mut fleet: Vec<Player> = ships
+        .iter()
+        .map(|(player, _, _)| (*player).clone())
+        .collect();
+    let (mode, tick) = (current.ship_mode, game_state.tick);
+    step_fleet(&mut fleet, &q_ray, mode, &grid, &wells, tick, SIM_STEP);
+    for ((mut player, _, mut transform), stepped) in ships.into_iter().zip(fleet) {
+        *player = stepped;
+        transform.translation = Vec3::new(player.x * TILE_SIZE, -player.y * TILE_SIZE, 200.);
+    }
+}
 
-            if player_x >= ray.src_x + x_offset
-                && player_x <= ray.dest_x + x_offset
-                && player_y >= ray.src_y + y_offset
-                && player_y <= ray.dest_y + y_offset
-            {
-                if ray.prio > heighest_prio {
-                    continue;
-                }
-                heighest_prio = ray.prio;
-                if ray.horizontal {
-                    y_diff = 0.;
-                    if ray.reversed {
-                        x_diff = PLAYER_SPEED;
-                        new_direction = Some(Dir::Rightwards);
-                    } else {
-                        x_diff = -PLAYER_SPEED;
-                        new_direction = Some(Dir::Leftwards);
-                    }
-                } else {
-                    x_diff = 0.;
-                    if ray.reversed {
-                        y_diff = -PLAYER_SPEED;
-                        new_direction = Some(Dir::Upwards);
-                    } else {
-                        y_diff = PLAYER_SPEED;
-                        new_direction = Some(Dir::Downwards);
-                    }
-                }
+// the ships of the current level in fleet order, as move_player steps them
+fn fleet_order<'a>(ships: impl IntoIterator<Item = (&'a Player, &'a FleetMember)>) -> Vec<Player> {
+    let mut ships: Vec<_> = ships.into_iter().collect();
+    ships.sort_by_key(|(_, member)| member.index);
+    ships
+        .into_iter()
+        .map(|(player, _)| player.clone())
+        .collect()
+}
+
+// runs step_fleet on a copy of the fleet and returns the positions each ship
+// passes, one every PREDICTION_SPACING tiles, until no ship moves or each
+// covered `tiles`, starting on `tick` in the current level
+fn predict_paths(
+    ships: &[Player],
+    rays: &[&Ray],
+    mode: ShipMode,
+    grid: &LevelGrid,
+    wells: &[GravityWell],
+    mut tick: u32,
+    tiles: f32,
+) -> Vec<Vec<Vec2>> {
+    let mut fleet = ships.to_vec();
+    let mut points = vec![Vec::new(); fleet.len()];
+    let mut travelled = vec![0.; fleet.len()];
+    let mut since_last_point = vec![0.; fleet.len()];
+    while travelled.iter().any(|travelled| *travelled < tiles) {
+        let before: Vec<(f32, f32)> = fleet.iter().map(|ship| (ship.x, ship.y)).collect();
+        let rays = rays.iter().copied();
+        step_fleet(&mut fleet, rays, mode, grid, wells, tick, SIM_STEP);
+        tick += 1;
+        let mut any_moved = false;
+        for (index, (ship, (x, y))) in fleet.iter().zip(before).enumerate() {
+            let moved = (ship.x - x).abs() + (ship.y - y).abs();
+            if moved == 0. || travelled[index] >= tiles {
+                continue;
+            }
+            any_moved = true;
+            travelled[index] += moved;
+            since_last_point[index] += moved;
+            if since_last_point[index] >= PREDICTION_SPACING {
+                since_last_point[index] -= PREDICTION_SPACING;
+                points[index].push(Vec2::new(ship.x, ship.y));
             }
         }
-
-        player.x += time.delta().as_secs_f32() * x_diff;
-        player.y += time.delta().as_secs_f32() * y_diff;
-        // if (player.x as i32) != player_x || (player.y as i32) != player_y {
-        // }
-        if player.direction != new_direction {
-            player.last_direction = player.direction.clone();
-            player.direction = new_direction;
+        if !any_moved {
+            break;
         }
-        transform.translation = Vec3::new(player.x * TILE_SIZE, -player.y * TILE_SIZE, 200.);
     }
+    points
 }
 
-fn spawn_ray(mut src_x: i32, mut src_y: i32, mut dest_x: i32, mut dest_y: i32, prio: i32, commands: &mut Commands) {
-    let horizontal = dest_y == src_y;
-    let mut reversed = true;
+fn advance_tick(mut game_state: ResMut<GameState>) {
+    game_state.tick += 1;
+}
 
-    if src_x > dest_x {
-        let tmp = src_x;
-        src_x = dest_x;
-        dest_x = tmp;
-        reversed = false;
+fn check_goal(
+    grid: Res<LevelGrid>,
+    current: Res<CurrentLevel>,
+    mut game_state: ResMut<GameState>,
+    q_player: Query<(&Player, &FleetMember)>,
+    mut solved: EventWriter<LevelSolved>,
+) {
+    for (player, member) in &q_player {
+        let arrived = game_state.arrived.contains(&member.index);
+        if !arrived && member.on_goal(player, &grid, current.rect) {
+            game_state.arrived.push(member.index);
+        }
     }
-
-    if src_y >= dest_y {
-        let tmp = src_y;
-        src_y = dest_y;
-        dest_y = tmp;
-    } else {
-        reversed = false;
+    let fleet_size = q_player.iter().count();
+    if fleet_size == 0 || game_state.arrived.len() < fleet_size {
+        return;
     }
-
-    let mut rot = 3.14;
-    if reversed {
-        rot = 0.;
+    if game_state.solved.insert(current.iid.clone()) {
+        solved.send(LevelSolved {
+            iid: current.iid.clone(),
+            clicks: game_state.activations,
+            time: game_state.tick as f32 * SIM_STEP,
+            collected: collected_here(&game_state, &current),
+        });
     }
-    if !horizontal {
-        rot += 3.14 / 2.;
+}
+
+impl Ray {
+    fn new(mut src_x: i32, mut src_y: i32, mut dest_x: i32, mut dest_y: i32, prio: i32) -> Self {
+        let horizontal = dest_y == src_y;
+        let mut reversed = true;
+
+        if src_x > dest_x {
+            std::mem::swap(&mut src_x, &mut dest_x);
+            reversed = false;
+        }
+
+        if src_y >= dest_y {
+            std::mem::swap(&mut src_y, &mut dest_y);
+        } else {
+            reversed = false;
+        }
+
+        Ray {
+            src_x,
+            src_y,
+            dest_x,
+            dest_y,
+            horizontal,
+            reversed,
+            prio,
+            timing: BeamTiming::default(),
+            born: 0,
+            intensity: 1.,
+            switched_off: false,
+        }
     }
 
-    // let prio = game_state.ray_count;
-    commands.spawn(Ray {
-        src_x,
-        src_y,
-        dest_x,
-        dest_y,
-        horizontal,
-        reversed,
-        prio,
-    });
-
-    let mut light_ray_handle: Option<Handle<TextureAtlas>> = None;
-    unsafe {
-        light_ray_handle = light_ray_texture_handle.clone();
+    fn rotation(&self) -> f32 {
+        let mut rot = PI;
+        if self.reversed {
+            rot = 0.;
+        }
+        if !self.horizontal {
+            rot += PI / 2.;
+        }
+        rot
     }
+}
 
-    for x in src_x..=dest_x {
-        for y in src_y..=dest_y {
-            let mut transform = Transform::from_xyz(
-                x as f32 * TILE_SIZE,
-                -y as f32 * TILE_SIZE,
-                50. - prio as f32,
-            );
+fn spawn_ray(ray: Ray, game_assets: &GameAssets, commands: &mut Commands) -> Entity {
+    let color = ray_color(ray.prio);
+    let sprites = spawn_ray_sprites(&ray, color, game_assets, commands);
+    commands
+        .spawn((ray, SpatialBundle::default()))
+        .push_children(&sprites)
+        .id()
+}
+
+fn ray_color(prio: i32) -> Color {
+    RAY_COLORS[prio.rem_euclid(RAY_COLORS.len() as i32) as usize]
+}
+
+// spawns one animated photon sprite per tile covered by the ray
+fn spawn_ray_sprites(
+    ray: &Ray,
+    color: Color,
+    game_assets: &GameAssets,
+    commands: &mut Commands,
+) -> Vec<Entity> {
+    let rot = ray.rotation();
+
+    let mut sprites = Vec::new();
+    for x in ray.src_x..=ray.dest_x {
+        for y in ray.src_y..=ray.dest_y {
+            let mut transform =
+                Transform::from_xyz(x as f32 * TILE_SIZE, -y as f32 * TILE_SIZE, ray_z(ray.prio));
             transform.rotate_z(rot);
             let index = AnimationIndex { first: 0, last: 5 };
-            commands.spawn((
+            let sprite = commands.spawn((
                 SpriteSheetBundle {
-                    texture_atlas: light_ray_handle.clone().unwrap(),
+                    texture_atlas: game_assets.light_ray_atlas.clone(),
                     transform,
                     sprite: TextureAtlasSprite {
                         index: index.first,
                         // TODO: fix tint
-                        color: RAY_COLORS[prio as usize % RAY_COLORS.len()],
+                        color,
                         ..Default::default()
                     },
                     ..Default::default()
@@ -447,66 +689,156 @@ fn spawn_ray(mut src_x: i32, mut src_y: i32, mut dest_x: i32, mut dest_y: i32, p
                 index,
                 AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
             ));
+            sprites.push(sprite.id());
         }
     }
+    sprites
+}
+
+// follows the caster's direction until the beam hits something or leaves the level
+fn trace_beam(ray_caster: &RayCaster, grid: &LevelGrid) -> (i32, i32) {
+    let (step_x, step_y) = ray_caster.dir.step();
+    let (mut x, mut y) = (ray_caster.pos_x, ray_caster.pos_y);
+    while grid.contains(x + step_x, y + step_y) && !grid.blocks_beam(x + step_x, y + step_y) {
+        x += step_x;
+        y += step_y;
+    }
+    (x, y)
+}
+
+// the ray a caster fires, reaching as far as its beam
+fn caster_ray(ray_caster: &RayCaster, grid: &LevelGrid, prio: i32) -> Ray {
+    let (dest_x, dest_y) = trace_beam(ray_caster, grid);
+    Ray::new(ray_caster.pos_x, ray_caster.pos_y, dest_x, dest_y, prio)
+}
+
+// the same ray traced again from where its caster is now
+fn retrace_ray(ray: &Ray, ray_caster: &RayCaster, grid: &LevelGrid) -> Ray {
+    Ray {
+        switched_off: ray.switched_off,
+        ..caster_ray(ray_caster, grid, ray.prio).timed(ray.timing.clone(), ray.born)
+    }
+}
+
+// the ray the caster of level `iid` fires on tick `born`, with the prio it
+// gets after `ray_count` other rays, see RayPriority
+fn fired_ray(
+    ray_caster: &RayCaster,
+    iid: &str,
+    timing: Option<&BeamTiming>,
+    grid: &LevelGrid,
+    priority: &RayPriority,
+    ray_count: i32,
+    born: u32,
+) -> Ray {
+    let prio = priority.fired(iid, ray_caster.pos_x, ray_caster.pos_y, ray_count);
+    caster_ray(ray_caster, grid, prio).timed(timing.cloned().unwrap_or_default(), born)
+}
+
+// fires the caster's beam as a new ray that exists from tick `born` on,
+// tagged with the caster's level
+#[allow(clippy::too_many_arguments)]
+fn fire_caster(
+    ray_caster: &RayCaster,
+    caster: Entity,
+    timing: Option<&BeamTiming>,
+    level_entity: &LevelEntity,
+    grid: &LevelGrid,
+    priority: &RayPriority,
+    game_assets: &GameAssets,
+    game_state: &mut GameState,
+    born: u32,
+    commands: &mut Commands,
+) {
+    let ray = fired_ray(
+        ray_caster,
+        &level_entity.0,
+        timing,
+        grid,
+        priority,
+        game_state.ray_count,
+        born,
+    );
+    let ray = spawn_ray(ray, game_assets, commands);
+    commands
+        .entity(ray)
+        .insert((level_entity.clone(), Beam { caster }));
+    game_state.ray_count += 1;
+}
+
+// fires the caster for the player or a replay, counted as a click
+#[allow(clippy::too_many_arguments)]
+fn activate_caster(
+    ray_caster: &RayCaster,
+    caster: Entity,
+    timing: Option<&BeamTiming>,
+    level_entity: &LevelEntity,
+    grid: &LevelGrid,
+    priority: &RayPriority,
+    game_assets: &GameAssets,
+    game_state: &mut GameState,
+    commands: &mut Commands,
+) -> CasterActivated {
+    let tick = game_state.tick;
+    fire_caster(
+        ray_caster,
+        caster,
+        timing,
+        level_entity,
+        grid,
+        priority,
+        game_assets,
+        game_state,
+        tick,
+        commands,
+    );
+    game_state.activations += 1;
+    CasterActivated {
+        x: ray_caster.pos_x,
+        y: ray_caster.pos_y,
+        tick: game_state.tick,
+    }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn update_hover_tint(
     pick_state: Res<PickState>,
-    mut q_sprite: Query<(&mut Sprite, Entity, &RayCaster)>,
-    mouse: Res<Input<MouseButton>>,
+    mut q_sprite: Query<(
+        &mut Sprite,
+        Entity,
+        &mut RayCaster,
+        Option<&mut MovingCaster>,
+        Option<&BeamTiming>,
+        &LevelEntity,
+    )>,
+    mut grid: ResMut<LevelGrid>,
+    priority: Res<RayPriority>,
+    game_assets: Res<GameAssets>,
     mut game_state: ResMut<GameState>,
+    mut activated: EventWriter<CasterActivated>,
     mut commands: Commands,
 ) {
-    for (mut sprite, entity, ray_caster) in &mut q_sprite {
+    for (mut sprite, entity, mut ray_caster, moving, timing, level_entity) in &mut q_sprite {
         if pick_state.selected.is_some() && pick_state.selected.unwrap() == entity {
             sprite.color = Color::rgb(1.2, 1.2, 1.2);
 
-            if mouse.just_pressed(MouseButton::Left) {
-                let prio = game_state.ray_count;
-                match ray_caster.dir {
-                    Dir::Upwards => {
-                        spawn_ray(
-                            ray_caster.pos_x,
-                            ray_caster.pos_y,
-                            ray_caster.pos_x,
-                            -LEVEL_SIZE_Y as i32,
-                            prio,
-                            &mut commands
-                        );
-                    }
-                    Dir::Downwards => {
-                        spawn_ray(
-                            ray_caster.pos_x,
-                            ray_caster.pos_y,
-                            ray_caster.pos_x,
-                            LEVEL_SIZE_Y as i32,
-                            prio,
-                            &mut commands
-                        );
-                    }
-                    Dir::Leftwards => {
-                        spawn_ray(
-                            ray_caster.pos_x,
-                            ray_caster.pos_y,
-                            -LEVEL_SIZE_X as i32,
-                            ray_caster.pos_y,
-                            prio,
-                            &mut commands
-                        );
-                    }
-                    Dir::Rightwards => {
-                        spawn_ray(
-                            ray_caster.pos_x,
-                            ray_caster.pos_y,
-                            LEVEL_SIZE_X as i32,
-                            ray_caster.pos_y,
-                            prio,
-                            &mut commands
-                        );
-                    }
-                }
-                game_state.ray_count += 1;
+            if pick_state.activated == Some(entity) {
+                click_caster(
+                    &mut ray_caster,
+                    moving.map(|moving| moving.into_inner()),
+                    &mut grid,
+                );
+                activated.send(activate_caster(
+                    &ray_caster,
+                    entity,
+                    timing,
+                    level_entity,
+                    &grid,
+                    &priority,
+                    &game_assets,
+                    &mut game_state,
+                    &mut commands,
+                ));
             }
         } else {
             sprite.color = Color::rgb(1., 1., 1.);
@@ -514,27 +846,107 @@ fn update_hover_tint(
     }
 }
 
+#[derive(States, Default, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AppState {
+    #[default]
+    Loading,
+    LevelSelect,
+    InGame,
+}
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug, SystemSet)]
 pub enum GameSystemSets {
     Input,
     Logic,
 }
 
+// sets of the fixed timestep schedule, Input is applied before every Step
+#[derive(Clone, Eq, PartialEq, Hash, Debug, SystemSet)]
+pub enum SimulationSets {
+    Input,
+    Step,
+}
+
 fn main() {
+    // `--replay <file>` plays a recorded session, `--verify <file>` checks it without a window,
+    // `--lint` checks the levels of the project, `--generate <seed> [<width>x<height>] [<clicks>]`
+    // adds a generated level to it
+    let args: Vec<String> = std::env::args().collect();
+    let mut playback = None;
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("--verify"), Some(path)) => std::process::exit(verify_command(Path::new(path))),
+        (Some("--lint"), _) => std::process::exit(lint_command()),
+        (Some("--generate"), _) => std::process::exit(generate_command(&args[2..])),
+        (Some("--replay"), Some(path)) => playback = Some(PathBuf::from(path)),
+        _ => (),
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .add_plugin(GamePlugin)
+        .add_plugin(CasterPlugin)
+        .add_plugin(HazardPlugin)
+        .add_plugin(TriggerPlugin)
+        .add_plugin(CollectiblePlugin)
+        .add_plugin(FleetPlugin)
+        .add_plugin(ShipPlugin)
+        .add_plugin(LoadingPlugin)
+        .add_plugin(GameCameraPlugin)
         .add_plugin(PickingPlugin)
-        .insert_resource(GameState::default())
-
-        .add_startup_system(render_map.after(setup_textures))
-        .add_startup_system(setup_player)
-        .add_startup_system(setup_camera)
-        .add_startup_system(setup_textures)
-
-        .configure_set(GameSystemSets::Input)
-        .configure_set(GameSystemSets::Logic.after(GameSystemSets::Input))
-        .add_systems(
-            (update_animations, move_player, update_hover_tint).in_set(GameSystemSets::Logic),
-        )
+        .add_plugin(PreviewPlugin)
+        .add_plugin(WorldStreamingPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(LevelSelectPlugin)
+        .add_plugin(ScorePlugin)
+        .add_plugin(ReplayPlugin { playback })
         .run();
 }
+
+// level assets, app state, system sets and the ship simulation the other plugins build on
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>()
+            .add_asset::<LdtkProject>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<LdtkProjectLoader>()
+            .add_state::<AppState>()
+            .add_event::<LevelSolved>()
+            .add_event::<CasterActivated>()
+            .add_event::<ShipLost>()
+            .insert_resource(LevelGrid::default())
+            .insert_resource(TriggerNetwork::default())
+            .insert_resource(RayPriority::default())
+            .insert_resource(GameState::default())
+            .insert_resource(FixedTime::new_from_secs(SIM_STEP))
+            .configure_set(GameSystemSets::Input.run_if(in_state(AppState::InGame)))
+            .configure_set(
+                GameSystemSets::Logic
+                    .after(GameSystemSets::Input)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                schedule.configure_set(
+                    SimulationSets::Input
+                        .before(SimulationSets::Step)
+                        .run_if(in_state(AppState::InGame)),
+                );
+                schedule.configure_set(SimulationSets::Step.run_if(in_state(AppState::InGame)));
+            })
+            .add_systems((update_animations, update_hover_tint).in_set(GameSystemSets::Logic))
+            // rays spawned by simulation input have to exist before the ship steps
+            .add_system(
+                apply_system_buffers
+                    .after(SimulationSets::Input)
+                    .before(SimulationSets::Step)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (move_player, advance_tick, check_goal)
+                    .chain()
+                    .in_set(SimulationSets::Step)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{Dir, Player, Ray, PLAYER_SPEED};
+
+// Junctions
+//
+// Tile centres sit on whole coordinates and the ship moves from centre to
+// centre. It only ever decides where to go when it is exactly on a centre and
+// then follows the top ray leading on from that tile, the one with the
+// smallest prio:
+// - crossing: a perpendicular ray below the ship's own ray is passed straight
+//   through, one on top turns the ship onto it
+// - merging: where rays on one axis overlap the ship takes the direction of
+//   the top one, so a beam on top pointing the other way sends it back
+// - blocking: beams end before obstacles and casters but never block each
+//   other, the ship stops on the centre of the last tile of its ray and a ray
+//   ending on a tile does not turn ships there
+// Rays with the same prio are ranked by keeping the ship's direction first,
+// then in Dir order. Rays that are not lit on the tick the ship reaches a
+// centre are ignored there, see pulse.rs. Between two centres the ship keeps
+// going regardless. Where no lit ray leads on, a gravity well may still pull
+// the ship on, see hazards.rs.
+
+impl Ray {
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        (self.src_x..=self.dest_x).contains(&x) && (self.src_y..=self.dest_y).contains(&y)
+    }
+
+    // the ray carries ships from the centre of x, y to the next tile
+    fn leads_on(&self, x: i32, y: i32) -> bool {
+        let (step_x, step_y) = self.dir().step();
+        self.covers(x, y) && self.covers(x + step_x, y + step_y)
+    }
+
+    pub fn dir(&self) -> Dir {
+        match (self.horizontal, self.reversed) {
+            (true, true) => Dir::Rightwards,
+            (true, false) => Dir::Leftwards,
+            (false, true) => Dir::Upwards,
+            (false, false) => Dir::Downwards,
+        }
+    }
+}
+
+impl Player {
+    // the tile whose centre is closest to the ship
+    pub fn tile(&self) -> (i32, i32) {
+        (self.x.round() as i32, self.y.round() as i32)
+    }
+
+    // the tiles the ship is on or moving between, other ships keep out of them
+    pub fn claims(&self) -> [(i32, i32); 2] {
+        [
+            (self.x.floor() as i32, self.y.floor() as i32),
+            (self.x.ceil() as i32, self.y.ceil() as i32),
+        ]
+    }
+
+    fn on_centre(&self) -> bool {
+        self.x.fract() == 0. && self.y.fract() == 0.
+    }
+}
+
+// where a ship standing on the centre of tile x, y goes next, None to stop
+pub fn junction<'a>(
+    rays: impl IntoIterator<Item = &'a Ray>,
+    x: i32,
+    y: i32,
+    direction: Option<&Dir>,
+) -> Option<Dir> {
+    rays.into_iter()
+        .filter(|ray| ray.leads_on(x, y))
+        .map(|ray| (ray.prio, ray.dir()))
+        .min_by_key(|(prio, dir)| (*prio, Some(dir) != direction, dir.clone() as u8))
+        .map(|(_, dir)| dir)
+}
+
+// advances the ship by `delta` seconds on `tick`, turning only on tile centres,
+// `pull` says where a centre sends ships no lit ray carries on. The ship waits
+// on its centre while the next tile is one of `ships`.
+pub fn step_player<'a, I>(
+    player: &mut Player,
+    rays: I,
+    pull: impl Fn(i32, i32) -> Option<Dir>,
+    ships: &[(i32, i32)],
+    tick: u32,
+    delta: f32,
+) where
+    I: IntoIterator<Item = &'a Ray> + Clone,
+{
+    let mut remaining = delta * PLAYER_SPEED;
+    while remaining > 0. {
+        if player.on_centre() {
+            let (x, y) = player.tile();
+            let lit = rays.clone().into_iter().filter(|ray| ray.lit(tick));
+            player.direction =
+                junction(lit, x, y, player.direction.as_ref()).or_else(|| pull(x, y));
+            let blocked = player.direction.as_ref().is_some_and(|direction| {
+                let (step_x, step_y) = direction.step();
+                ships.contains(&(x + step_x, y + step_y))
+            });
+            if blocked {
+                player.direction = None;
+            }
+        }
+        let Some(direction) = &player.direction else {
+            break;
+        };
+
+        let (step_x, step_y) = direction.step();
+        let along = if step_x != 0 {
+            &mut player.x
+        } else {
+            &mut player.y
+        };
+        let sign = (step_x + step_y) as f32;
+        let next_centre = if sign > 0. {
+            along.floor() + 1.
+        } else {
+            along.ceil() - 1.
+        };
+        let to_centre = (next_centre - *along).abs();
+        if remaining < to_centre {
+            *along += sign * remaining;
+            break;
+        }
+        // land exactly on the centre so the next junction is decided there
+        *along = next_centre;
+        remaining -= to_centre;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec2;
+
+    use super::*;
+
+    // a ship at tile 5, 5 facing `direction`
+    fn ship(direction: Option<Dir>) -> Player {
+        Player {
+            x: 5.,
+            y: 5.,
+            direction,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    fn no_pull(_: i32, _: i32) -> Option<Dir> {
+        None
+    }
+
+    // a ray shot from src towards dest
+    fn ray(src: (i32, i32), dest: (i32, i32), prio: i32) -> Ray {
+        Ray::new(src.0, src.1, dest.0, dest.1, prio)
+    }
+
+    // rays through tile 5, 5 along each direction
+    fn through_centre(dir: &Dir, prio: i32) -> Ray {
+        match dir {
+            Dir::Upwards => ray((5, 9), (5, 0), prio),
+            Dir::Downwards => ray((5, 0), (5, 9), prio),
+            Dir::Leftwards => ray((9, 5), (0, 5), prio),
+            Dir::Rightwards => ray((0, 5), (9, 5), prio),
+        }
+    }
+
+    const DIRS: [Dir; 4] = [
+        Dir::Upwards,
+        Dir::Downwards,
+        Dir::Leftwards,
+        Dir::Rightwards,
+    ];
+
+    fn perpendicular(a: &Dir, b: &Dir) -> bool {
+        let ((ax, _), (bx, _)) = (a.step(), b.step());
+        (ax == 0) != (bx == 0)
+    }
+
+    #[test]
+    fn rays_point_where_they_were_shot() {
+        for dir in &DIRS {
+            assert!(through_centre(dir, 0).dir() == *dir);
+        }
+    }
+
+    #[test]
+    fn ship_turns_onto_a_crossing_ray_on_top_in_every_direction() {
+        for from in &DIRS {
+            for to in DIRS.iter().filter(|to| perpendicular(from, to)) {
+                let rays = [through_centre(from, 1), through_centre(to, 0)];
+                // one tile before the junction, facing it
+                let (step_x, step_y) = from.step();
+                let mut player = ship(Some(from.clone()));
+                player.x -= step_x as f32;
+                player.y -= step_y as f32;
+
+                step_player(&mut player, &rays, no_pull, &[], 0, 1.5 / PLAYER_SPEED);
+
+                let (turn_x, turn_y) = to.step();
+                assert!(player.direction.as_ref() == Some(to));
+                assert_eq!(player.x, 5. + turn_x as f32 * 0.5);
+                assert_eq!(player.y, 5. + turn_y as f32 * 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn ship_crosses_a_ray_below_its_own() {
+        for from in &DIRS {
+            for other in DIRS.iter().filter(|other| perpendicular(from, other)) {
+                let rays = [through_centre(from, 0), through_centre(other, 1)];
+                let mut player = ship(Some(from.clone()));
+                step_player(&mut player, &rays, no_pull, &[], 0, 1. / PLAYER_SPEED);
+
+                let (step_x, step_y) = from.step();
+                assert!(player.direction.as_ref() == Some(from));
+                assert_eq!(player.tile(), (5 + step_x, 5 + step_y));
+            }
+        }
+    }
+
+    #[test]
+    fn ship_turns_only_on_tile_centres() {
+        let rays = [ray((0, 5), (9, 5), 1), ray((6, 0), (6, 9), 0)];
+        let mut player = ship(Some(Dir::Rightwards));
+        player.x = 5.25;
+        // still short of the centre of 6, 5
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Rightwards));
+        assert_eq!((player.x, player.y), (5.75, 5.));
+
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Downwards));
+        assert_eq!((player.x, player.y), (6., 5.25));
+    }
+
+    #[test]
+    fn merging_ray_on_top_sends_the_ship_back() {
+        let rays = [ray((0, 5), (9, 5), 1), ray((8, 5), (3, 5), 0)];
+        let mut player = ship(Some(Dir::Rightwards));
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Leftwards));
+        assert_eq!(player.x, 4.5);
+    }
+
+    #[test]
+    fn merging_ray_below_is_ignored() {
+        let rays = [ray((0, 5), (9, 5), 0), ray((8, 5), (3, 5), 1)];
+        let mut player = ship(Some(Dir::Rightwards));
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Rightwards));
+        assert_eq!(player.x, 5.5);
+    }
+
+    #[test]
+    fn ship_stops_on_the_last_tile_of_a_blocked_ray() {
+        // the beam was blocked right after tile 7, 5
+        let rays = [ray((0, 5), (7, 5), 0)];
+        let mut player = ship(None);
+        step_player(&mut player, &rays, no_pull, &[], 0, 5. / PLAYER_SPEED);
+        assert!(player.direction.is_none());
+        assert_eq!((player.x, player.y), (7., 5.));
+    }
+
+    #[test]
+    fn ray_ending_on_a_tile_does_not_turn_the_ship() {
+        // on top, but it ends right on the ship's tile
+        let rays = [ray((0, 5), (9, 5), 1), ray((5, 0), (5, 5), 0)];
+        let mut player = ship(Some(Dir::Rightwards));
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Rightwards));
+    }
+
+    #[test]
+    fn equal_prios_keep_the_ship_going_straight() {
+        let rays = [ray((0, 5), (9, 5), 0), ray((5, 0), (5, 9), 0)];
+        let mut player = ship(Some(Dir::Rightwards));
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Rightwards));
+
+        let mut player = ship(Some(Dir::Downwards));
+        step_player(&mut player, &rays, no_pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert!(player.direction == Some(Dir::Downwards));
+    }
+
+    #[test]
+    fn ship_waits_on_a_centre_without_rays() {
+        let mut player = ship(None);
+        step_player(&mut player, &[], no_pull, &[], 0, 1.);
+        assert!(player.direction.is_none());
+        assert_eq!(player.tile(), (5, 5));
+    }
+
+    #[test]
+    fn pull_only_moves_ships_no_ray_carries_on() {
+        let pull = |_, _| Some(Dir::Upwards);
+        let mut player = ship(None);
+        step_player(&mut player, &[], pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert_eq!((player.x, player.y), (5., 4.5));
+
+        let rays = [through_centre(&Dir::Leftwards, 0)];
+        let mut player = ship(None);
+        step_player(&mut player, &rays, pull, &[], 0, 0.5 / PLAYER_SPEED);
+        assert_eq!((player.x, player.y), (4.5, 5.));
+    }
+}
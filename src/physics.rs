@@ -0,0 +1,238 @@
+use bevy::prelude::*;
+
+use crate::{
+    hazards::{grid_pull, sail_pull, GravityWell},
+    level::Level,
+    movement::step_player,
+    Dir, LevelGrid, Player, Ray,
+};
+
+// tiles per second squared a ray of intensity 1 adds to the ship's speed
+const SAIL_PUSH: f32 = 6.;
+// share of its speed the ship loses per second
+const SAIL_DRAG: f32 = 0.6;
+const SAIL_MAX_SPEED: f32 = 4.;
+// slower than this without any push the ship comes to rest
+const SAIL_REST_SPEED: f32 = 0.05;
+
+// How the ship moves in a level, picked with the LDtk level field `ship_mode`
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum ShipMode {
+    // centre to centre at PLAYER_SPEED along the top ray, see movement.rs
+    #[default]
+    Grid,
+    // a solar sail: every lit ray over the ship's tile pushes it along the
+    // ray by its intensity, the ship keeps its momentum and drifts on with
+    // drag when it leaves the beams. Obstacles, casters and asteroids stop it
+    Sail,
+}
+
+impl ShipMode {
+    pub fn from_level(level: &Level) -> Self {
+        match level.field("ship_mode").and_then(|value| value.as_str()) {
+            Some("Sail") => ShipMode::Sail,
+            _ => ShipMode::Grid,
+        }
+    }
+}
+
+impl Dir {
+    fn vec(&self) -> Vec2 {
+        let (x, y) = self.step();
+        Vec2::new(x as f32, y as f32)
+    }
+}
+
+// advances the ship by `delta` seconds on `tick` the way `mode` moves it,
+// keeping out of the tiles of other `ships`
+#[allow(clippy::too_many_arguments)]
+pub fn step_ship<'a, I>(
+    player: &mut Player,
+    rays: I,
+    mode: ShipMode,
+    grid: &LevelGrid,
+    wells: &[GravityWell],
+    ships: &[(i32, i32)],
+    tick: u32,
+    delta: f32,
+) where
+    I: IntoIterator<Item = &'a Ray> + Clone,
+{
+    match mode {
+        ShipMode::Grid => {
+            player.velocity = Vec2::ZERO;
+            let pull = |x, y| grid_pull(wells, grid, x, y);
+            step_player(player, rays, pull, ships, tick, delta);
+        }
+        ShipMode::Sail => step_sail(player, rays, grid, wells, ships, tick, delta),
+    }
+}
+
+fn step_sail<'a>(
+    player: &mut Player,
+    rays: impl IntoIterator<Item = &'a Ray>,
+    grid: &LevelGrid,
+    wells: &[GravityWell],
+    ships: &[(i32, i32)],
+    tick: u32,
+    delta: f32,
+) {
+    let (x, y) = player.tile();
+    let push: Vec2 = rays
+        .into_iter()
+        .filter(|ray| ray.lit(tick) && ray.covers(x, y))
+        .map(|ray| ray.dir().vec() * ray.intensity)
+        .sum();
+
+    let gravity = sail_pull(wells, player.x, player.y);
+
+    let mut velocity = player.velocity + (push * SAIL_PUSH + gravity) * delta;
+    velocity *= (1. - SAIL_DRAG * delta).max(0.);
+    velocity = velocity.clamp_length_max(SAIL_MAX_SPEED);
+    if push == Vec2::ZERO && gravity == Vec2::ZERO && velocity.length() < SAIL_REST_SPEED {
+        velocity = Vec2::ZERO;
+    }
+
+    // other ships only stop it from entering their tiles, so it never gets stuck on one
+    let blocked = |to: (i32, i32), from: (i32, i32)| {
+        grid.blocks_beam(to.0, to.1) || (to != from && ships.contains(&to))
+    };
+    // axis by axis, so the ship slides along walls it runs into
+    let moved_x = player.x + velocity.x * delta;
+    if blocked((moved_x.round() as i32, y), (x, y)) {
+        velocity.x = 0.;
+    } else {
+        player.x = moved_x;
+    }
+    let moved_y = player.y + velocity.y * delta;
+    let from = player.tile();
+    if blocked((player.x.round() as i32, moved_y.round() as i32), from) {
+        velocity.y = 0.;
+    } else {
+        player.y = moved_y;
+    }
+
+    player.velocity = velocity;
+    player.direction = heading(velocity);
+}
+
+// the direction the ship drifts in most, None at rest
+fn heading(velocity: Vec2) -> Option<Dir> {
+    if velocity == Vec2::ZERO {
+        None
+    } else if velocity.x.abs() >= velocity.y.abs() {
+        Some(if velocity.x > 0. {
+            Dir::Rightwards
+        } else {
+            Dir::Leftwards
+        })
+    } else {
+        Some(if velocity.y > 0. {
+            Dir::Downwards
+        } else {
+            Dir::Upwards
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{level::LevelRect, SIM_STEP};
+
+    fn ship() -> Player {
+        Player {
+            x: 5.,
+            y: 5.,
+            direction: None,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    // an open 10 by 10 grid with the given obstacles
+    fn grid(obstacles: &[(i32, i32)]) -> LevelGrid {
+        let mut grid = LevelGrid {
+            rect: LevelRect {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            },
+            tiles: vec![0; 100],
+        };
+        for (x, y) in obstacles {
+            grid.set(*x, *y, 1);
+        }
+        grid
+    }
+
+    fn sail(player: &mut Player, rays: &[Ray], grid: &LevelGrid, ticks: u32) {
+        for tick in 0..ticks {
+            step_ship(player, rays, ShipMode::Sail, grid, &[], &[], tick, SIM_STEP);
+        }
+    }
+
+    #[test]
+    fn beams_accelerate_the_ship() {
+        let rays = [Ray::new(0, 5, 9, 5, 0)];
+        let mut player = ship();
+        sail(&mut player, &rays, &grid(&[]), 10);
+        let early = player.velocity.x;
+        sail(&mut player, &rays, &grid(&[]), 10);
+        assert!(early > 0. && player.velocity.x > early);
+        assert_eq!(player.velocity.y, 0.);
+        assert!(player.direction == Some(Dir::Rightwards));
+    }
+
+    #[test]
+    fn stronger_beams_push_harder() {
+        let mut weak = ship();
+        sail(&mut weak, &[Ray::new(0, 5, 9, 5, 0)], &grid(&[]), 10);
+        let mut strong = ship();
+        let mut ray = Ray::new(0, 5, 9, 5, 0);
+        ray.intensity = 2.;
+        sail(&mut strong, &[ray], &grid(&[]), 10);
+        assert!(strong.velocity.x > weak.velocity.x * 1.9);
+    }
+
+    #[test]
+    fn ship_drifts_on_after_the_beam_and_slows_down() {
+        let mut player = ship();
+        player.velocity = Vec2::new(0., 2.);
+        sail(&mut player, &[], &grid(&[]), 30);
+        assert!(player.y > 5.5);
+        assert!(player.velocity.y > 0. && player.velocity.y < 2.);
+
+        sail(&mut player, &[], &grid(&[]), 60 * 30);
+        assert_eq!(player.velocity, Vec2::ZERO);
+        assert!(player.direction.is_none());
+    }
+
+    #[test]
+    fn obstacles_stop_the_ship_on_their_axis_only() {
+        let mut player = ship();
+        player.velocity = Vec2::new(2., 1.);
+        sail(&mut player, &[], &grid(&[(6, 5), (6, 6)]), 60);
+        assert_eq!(player.velocity.x, 0.);
+        assert!(player.x < 5.5);
+        assert!(player.y > 5.5);
+    }
+
+    #[test]
+    fn grid_mode_ignores_momentum() {
+        let mut player = ship();
+        player.velocity = Vec2::new(2., 0.);
+        step_ship(
+            &mut player,
+            &[],
+            ShipMode::Grid,
+            &grid(&[]),
+            &[],
+            &[],
+            0,
+            1.,
+        );
+        assert_eq!((player.x, player.y), (5., 5.));
+        assert_eq!(player.velocity, Vec2::ZERO);
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::{
+    input::touch::Touches,
     prelude::*,
     render::camera::RenderTarget,
     window::{PrimaryWindow, Window},
@@ -6,21 +7,88 @@ use bevy::{
 
 use crate::GameSystemSets;
 
+// a touch held longer than this previews instead of activating
+const LONG_PRESS_SECS: f32 = 0.4;
+// fingers wobble, so a tap may move a bit before it is released
+const TAP_MAX_DISTANCE: f32 = 24.;
+
 // Components
 
 #[derive(Component, Clone)]
 pub struct Pickable {
     pub triangles: Vec<Triangle>,
+    pub layers: PickLayers,
+    // disabled pickables keep their shape but are skipped while picking
+    pub enabled: bool,
+}
+
+impl Pickable {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        Pickable {
+            triangles,
+            layers: PickLayers::default(),
+            enabled: true,
+        }
+    }
+
+    pub fn with_layers(mut self, layers: PickLayers) -> Self {
+        self.layers = layers;
+        self
+    }
 }
 
 #[derive(Component, Default)]
-pub struct PickCamera;
+pub struct PickCamera {
+    // only pickables sharing a layer with the camera can be picked
+    pub layers: PickLayers,
+}
+
+/// Bitmask of up to 32 pick layers, numbered 0 to 31. Defaults to layer 0 only.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PickLayers(u32);
+
+impl PickLayers {
+    pub const fn layer(layer: u8) -> Self {
+        debug_assert!(layer < 32, "pick layers go from 0 to 31");
+        PickLayers(1 << layer)
+    }
+
+    pub fn intersects(&self, other: &PickLayers) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for PickLayers {
+    fn default() -> Self {
+        PickLayers::layer(0)
+    }
+}
 
 // Resources
 
 #[derive(Resource, Default)]
 pub struct PickState {
     pub selected: Option<Entity>,
+    // set for a single frame when the selected entity got clicked or tapped
+    pub activated: Option<Entity>,
+    // true while the selection comes from a long press on a touchscreen
+    pub long_press: bool,
+    // the selection comes from a touch, not from the cursor hovering over it
+    pub touch: bool,
+}
+
+impl PickState {
+    // what to preview: the entity under the cursor, on touchscreens the one
+    // held in a long press
+    pub fn previewed(&self) -> Option<Entity> {
+        self.selected.filter(|_| !self.touch || self.long_press)
+    }
+}
+
+#[derive(Default)]
+struct TrackedTouch {
+    id: Option<u64>,
+    held: f32,
 }
 
 // Plugin
@@ -34,13 +102,18 @@ impl Plugin for PickingPlugin {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn pick_input(
-    camera: Query<(&Camera, &GlobalTransform)>,
+    camera: Query<(&Camera, &GlobalTransform, &PickCamera)>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
     pickables: Query<(&Pickable, &GlobalTransform, Entity)>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    time: Res<Time>,
+    mut tracked_touch: Local<TrackedTouch>,
     mut pick_state: ResMut<PickState>,
 ) {
-    let (camera, camera_transform) = camera.single();
+    let (camera, camera_transform, pick_camera) = camera.single();
     // fuck off bevy docs
     let window = match camera.target {
         RenderTarget::Window(bevy::window::WindowRef::Primary) => primary_window.single(),
@@ -49,41 +122,100 @@ fn pick_input(
         _ => return,
     };
 
+    pick_state.activated = None;
+    pick_state.long_press = false;
+
+    // touch positions start at the top left, the cursor at the bottom left
+    let pick_touch = |touch_pos: Vec2| {
+        let viewport_pos = Vec2::new(touch_pos.x, window.height() - touch_pos.y);
+        camera
+            .viewport_to_world_2d(camera_transform, viewport_pos)
+            .and_then(|world_pos| pick_nearst(&pickables, &world_pos, &pick_camera.layers))
+    };
+
+    if tracked_touch.id.is_none() {
+        if let Some(touch) = touches.iter_just_pressed().next() {
+            tracked_touch.id = Some(touch.id());
+            tracked_touch.held = 0.;
+        }
+    }
+
+    if let Some(id) = tracked_touch.id {
+        pick_state.touch = true;
+        if let Some(touch) = touches.get_pressed(id) {
+            tracked_touch.held += time.delta_seconds();
+            if tracked_touch.held >= LONG_PRESS_SECS {
+                pick_state.selected = pick_touch(touch.position());
+                pick_state.long_press = true;
+            } else {
+                pick_state.selected = None;
+            }
+            return;
+        }
+
+        // released or cancelled, only a short tap activates
+        if let Some(touch) = touches.get_released(id) {
+            if tracked_touch.held < LONG_PRESS_SECS && touch.distance().length() <= TAP_MAX_DISTANCE
+            {
+                pick_state.selected = pick_touch(touch.position());
+                pick_state.activated = pick_state.selected;
+            } else {
+                pick_state.selected = None;
+            }
+        }
+        tracked_touch.id = None;
+        return;
+    }
+
     if let Some(cursor_pos) = window.cursor_position() {
+        pick_state.touch = false;
         if let Some(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor_pos) {
-            pick_state.selected = pick_nearst(&pickables, &world_pos);
+            pick_state.selected = pick_nearst(&pickables, &world_pos, &pick_camera.layers);
         }
     }
+    if mouse.just_pressed(MouseButton::Left) {
+        pick_state.activated = pick_state.selected;
+    }
 }
 
-fn pick_nearst(
-    pickables: &Query<(&Pickable, &GlobalTransform, Entity)>,
+// Triangles are closed, so a point on the diagonal shared by the two triangles
+// of a tile hits the tile. Of all pickables hit the one with the highest z wins,
+// on equal z the lower entity, so the result never depends on query order.
+fn pick_nearst<'a>(
+    pickables: impl IntoIterator<Item = (&'a Pickable, &'a GlobalTransform, Entity)>,
     world_pos: &Vec2,
+    layers: &PickLayers,
 ) -> Option<Entity> {
-    let mut nearest: Option<Entity> = None;
-    let mut distance = -1.;
-    for (pickable, transform, entity) in &(*pickables) {
+    let mut nearest: Option<(f32, Entity)> = None;
+    for (pickable, transform, entity) in pickables {
+        if !pickable.enabled || !pickable.layers.intersects(layers) {
+            continue;
+        }
+
         let obj_translation = transform.translation();
+        let in_front = nearest.is_none_or(|(z, nearest)| {
+            obj_translation.z > z || (obj_translation.z == z && entity < nearest)
+        });
+        if !in_front {
+            continue;
+        }
+
         let corrected_pos = Vec2::new(
             world_pos.x - obj_translation.x,
             world_pos.y - obj_translation.y,
         );
-
-        if distance >= 0. && obj_translation.z < distance {
-            continue;
-        }
-
-        for triangle in &pickable.triangles {
-            if triangle.contains(&corrected_pos) {
-                nearest = Some(entity);
-                distance = obj_translation.z;
-            }
+        if pickable
+            .triangles
+            .iter()
+            .any(|triangle| triangle.contains(&corrected_pos))
+        {
+            nearest = Some((obj_translation.z, entity));
         }
     }
-    nearest
+    nearest.map(|(_, entity)| entity)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Triangle {
     pub p1: Vec2,
     pub p2: Vec2,
@@ -102,7 +234,13 @@ impl Triangle {
     }
 
     // see: https://stackoverflow.com/questions/2049582/how-to-determine-if-a-point-is-in-a-2d-triangle
+    // Edges and corners are inside, whatever the winding. A degenerate triangle
+    // with all corners on one line has no inside and contains nothing.
     pub fn contains(&self, pt: &Vec2) -> bool {
+        if Self::sign(&self.p1, &self.p2, &self.p3) == 0. {
+            return false;
+        }
+
         let (d1, d2, d3) = (
             Self::sign(pt, &self.p1, &self.p2),
             Self::sign(pt, &self.p2, &self.p3),
@@ -115,3 +253,250 @@ impl Triangle {
         !(has_neg && has_pos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    // the two triangles every caster tile is picked with, split along y = x
+    fn tile_triangles() -> [Triangle; 2] {
+        [
+            Triangle::new(
+                Vec2::new(-16., 16.),
+                Vec2::new(16., 16.),
+                Vec2::new(-16., -16.),
+            ),
+            Triangle::new(
+                Vec2::new(16., 16.),
+                Vec2::new(-16., -16.),
+                Vec2::new(16., -16.),
+            ),
+        ]
+    }
+
+    fn tile(x: f32, y: f32, z: f32) -> (Pickable, GlobalTransform) {
+        (
+            Pickable::new(tile_triangles().to_vec()),
+            GlobalTransform::from_translation(Vec3::new(x, y, z)),
+        )
+    }
+
+    fn pick(pickables: &[(Pickable, GlobalTransform, Entity)], pos: Vec2) -> Option<Entity> {
+        pick_nearst(
+            pickables
+                .iter()
+                .map(|(pickable, transform, entity)| (pickable, transform, *entity)),
+            &pos,
+            &PickLayers::default(),
+        )
+    }
+
+    // barycentric coordinates in f64, None for degenerate triangles
+    fn barycentric(triangle: &Triangle, pt: Vec2) -> Option<[f64; 3]> {
+        let [x1, y1, x2, y2, x3, y3, px, py] = [
+            triangle.p1.x,
+            triangle.p1.y,
+            triangle.p2.x,
+            triangle.p2.y,
+            triangle.p3.x,
+            triangle.p3.y,
+            pt.x,
+            pt.y,
+        ]
+        .map(f64::from);
+        let denom = (y2 - y3) * (x1 - x3) + (x3 - x2) * (y1 - y3);
+        if denom == 0. {
+            return None;
+        }
+        let l1 = ((y2 - y3) * (px - x3) + (x3 - x2) * (py - y3)) / denom;
+        let l2 = ((y3 - y1) * (px - x3) + (x1 - x3) * (py - y3)) / denom;
+        Some([l1, l2, 1. - l1 - l2])
+    }
+
+    #[test]
+    fn tile_triangles_cover_the_tile_edges_included() {
+        let [upper, lower] = tile_triangles();
+        for x in -20i32..=20 {
+            for y in -20i32..=20 {
+                let pt = Vec2::new(x as f32, y as f32);
+                let in_tile = x.abs() <= 16 && y.abs() <= 16;
+                assert_eq!(upper.contains(&pt), in_tile && y >= x, "{}", pt);
+                assert_eq!(lower.contains(&pt), in_tile && y <= x, "{}", pt);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_diagonal_belongs_to_both_triangles() {
+        let [upper, lower] = tile_triangles();
+        for t in [-16., -8.5, 0., 0.25, 16.] {
+            let pt = Vec2::new(t, t);
+            assert!(upper.contains(&pt) && lower.contains(&pt), "{}", pt);
+        }
+    }
+
+    #[test]
+    fn winding_does_not_matter() {
+        let [triangle, _] = tile_triangles();
+        let reversed = Triangle::new(triangle.p3, triangle.p2, triangle.p1);
+        for x in -20i32..=20 {
+            for y in -20i32..=20 {
+                let pt = Vec2::new(x as f32, y as f32);
+                assert_eq!(triangle.contains(&pt), reversed.contains(&pt), "{}", pt);
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_contain_nothing() {
+        let line = Triangle::new(Vec2::ZERO, Vec2::new(4., 4.), Vec2::new(8., 8.));
+        let point = Triangle::new(Vec2::ONE, Vec2::ONE, Vec2::ONE);
+        for pt in [Vec2::ZERO, Vec2::new(2., 2.), Vec2::new(8., 8.), Vec2::ONE] {
+            assert!(!line.contains(&pt), "{}", pt);
+            assert!(!point.contains(&pt), "{}", pt);
+        }
+    }
+
+    #[test]
+    fn highest_z_wins_in_any_order() {
+        let (back, back_transform) = tile(0., 0., 1.);
+        let (front, front_transform) = tile(8., 0., 2.);
+        let mut pickables = vec![
+            (back, back_transform, Entity::from_raw(0)),
+            (front, front_transform, Entity::from_raw(1)),
+        ];
+        assert_eq!(
+            pick(&pickables, Vec2::new(4., 0.)),
+            Some(Entity::from_raw(1))
+        );
+        pickables.reverse();
+        assert_eq!(
+            pick(&pickables, Vec2::new(4., 0.)),
+            Some(Entity::from_raw(1))
+        );
+        // only the back tile is under this point
+        assert_eq!(
+            pick(&pickables, Vec2::new(-12., 0.)),
+            Some(Entity::from_raw(0))
+        );
+    }
+
+    #[test]
+    fn negative_z_is_ordered_like_any_other() {
+        let (back, back_transform) = tile(0., 0., -5.);
+        let (front, front_transform) = tile(0., 0., -1.);
+        let mut pickables = vec![
+            (front, front_transform, Entity::from_raw(0)),
+            (back, back_transform, Entity::from_raw(1)),
+        ];
+        assert_eq!(pick(&pickables, Vec2::ZERO), Some(Entity::from_raw(0)));
+        pickables.reverse();
+        assert_eq!(pick(&pickables, Vec2::ZERO), Some(Entity::from_raw(0)));
+    }
+
+    #[test]
+    fn lower_entity_wins_ties_in_z() {
+        // neighbouring tiles share the border at x = 16
+        let (left, left_transform) = tile(0., 0., 1.);
+        let (right, right_transform) = tile(32., 0., 1.);
+        let mut pickables = vec![
+            (right, right_transform, Entity::from_raw(3)),
+            (left, left_transform, Entity::from_raw(7)),
+        ];
+        assert_eq!(
+            pick(&pickables, Vec2::new(16., 0.)),
+            Some(Entity::from_raw(3))
+        );
+        pickables.reverse();
+        assert_eq!(
+            pick(&pickables, Vec2::new(16., 0.)),
+            Some(Entity::from_raw(3))
+        );
+        assert_eq!(
+            pick(&pickables, Vec2::new(15., 0.)),
+            Some(Entity::from_raw(7))
+        );
+    }
+
+    #[test]
+    fn disabled_and_other_layers_are_skipped() {
+        let (mut disabled, disabled_transform) = tile(0., 0., 3.);
+        disabled.enabled = false;
+        let (other_layer, other_transform) = tile(0., 0., 2.);
+        let (visible, visible_transform) = tile(0., 0., 1.);
+        let pickables = vec![
+            (disabled, disabled_transform, Entity::from_raw(0)),
+            (
+                other_layer.with_layers(PickLayers::layer(1)),
+                other_transform,
+                Entity::from_raw(1),
+            ),
+            (visible, visible_transform, Entity::from_raw(2)),
+        ];
+        assert_eq!(pick(&pickables, Vec2::ZERO), Some(Entity::from_raw(2)));
+        assert_eq!(pick(&pickables, Vec2::new(17., 0.)), None);
+    }
+
+    fn vec2(range: f32) -> impl Strategy<Value = Vec2> {
+        (-range..range, -range..range).prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    fn triangle() -> impl Strategy<Value = Triangle> {
+        (vec2(100.), vec2(100.), vec2(100.)).prop_map(|(p1, p2, p3)| Triangle::new(p1, p2, p3))
+    }
+
+    proptest! {
+        #[test]
+        fn contains_matches_barycentric_reference(triangle in triangle(), pt in vec2(120.)) {
+            let Some(weights) = barycentric(&triangle, pt) else {
+                return Ok(());
+            };
+            // f32 rounding decides points right next to an edge either way
+            let area = Triangle::sign(&triangle.p1, &triangle.p2, &triangle.p3).abs() as f64;
+            prop_assume!(weights.iter().all(|weight| (weight * area).abs() > 0.1));
+            let inside = weights.iter().all(|weight| *weight > 0.);
+            prop_assert_eq!(triangle.contains(&pt), inside);
+        }
+
+        #[test]
+        fn corners_are_inside(triangle in triangle()) {
+            prop_assume!(Triangle::sign(&triangle.p1, &triangle.p2, &triangle.p3) != 0.);
+            for corner in [triangle.p1, triangle.p2, triangle.p3] {
+                prop_assert!(triangle.contains(&corner));
+            }
+        }
+
+        #[test]
+        fn picks_the_frontmost_hit_regardless_of_order(
+            tiles in prop::collection::vec((-4i32..4, -4i32..4, -3i32..3), 1..12),
+            pt in vec2(160.),
+        ) {
+            let mut pickables: Vec<_> = tiles
+                .iter()
+                .enumerate()
+                .map(|(index, (x, y, z))| {
+                    let (pickable, transform) = tile(*x as f32 * 32., *y as f32 * 32., *z as f32);
+                    (pickable, transform, Entity::from_raw(index as u32))
+                })
+                .collect();
+            let expected = pickables
+                .iter()
+                .filter(|(_, transform, _)| {
+                    let offset = pt - transform.translation().truncate();
+                    offset.x.abs() <= 16. && offset.y.abs() <= 16.
+                })
+                .max_by(|(_, a, a_entity), (_, b, b_entity)| {
+                    a.translation()
+                        .z
+                        .total_cmp(&b.translation().z)
+                        .then(b_entity.cmp(a_entity))
+                })
+                .map(|(_, _, entity)| *entity);
+            prop_assert_eq!(pick(&pickables, pt), expected);
+            pickables.reverse();
+            prop_assert_eq!(pick(&pickables, pt), expected);
+        }
+    }
+}
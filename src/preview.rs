@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+
+use crate::{
+    caster::MovingCaster,
+    fired_ray,
+    fleet::FleetMember,
+    fleet_order,
+    hazards::GravityWell,
+    loading::GameAssets,
+    picking::PickState,
+    predict_paths,
+    priority::RayPriority,
+    pulse::BeamTiming,
+    ray_color, spawn_ray_sprites,
+    world::{CurrentLevel, LevelEntity},
+    AppState, GameState, GameSystemSets, LevelGrid, Player, Ray, RayCaster, TILE_SIZE,
+};
+
+// how far ahead the ghosted ship path is drawn, in tiles
+const PREVIEW_PATH_TILES: f32 = 24.;
+const PREVIEW_ALPHA: f32 = 0.35;
+const PREVIEW_DOT_SIZE: f32 = 6.;
+const TRAJECTORY_COLOR: Color = Color::rgba(1., 1., 1., 0.5);
+const TRAJECTORY_DOT_SIZE: f32 = 4.;
+
+// Components
+
+// everything spawned for the current preview, despawned when it changes
+#[derive(Component)]
+pub struct BeamPreview;
+
+// dots of the ship's predicted path under the active rays
+#[derive(Component)]
+struct TrajectoryDot;
+
+// Resources
+
+#[derive(Resource)]
+pub struct TrajectoryOverlay {
+    pub enabled: bool,
+    // how far ahead the path is predicted, in tiles
+    pub tiles_ahead: f32,
+}
+
+impl Default for TrajectoryOverlay {
+    fn default() -> Self {
+        TrajectoryOverlay {
+            enabled: true,
+            tiles_ahead: 16.,
+        }
+    }
+}
+
+// Plugin
+
+pub struct PreviewPlugin;
+
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrajectoryOverlay>();
+        app.add_system(toggle_trajectory.in_set(GameSystemSets::Input));
+        app.add_systems((update_beam_preview, update_trajectory).in_set(GameSystemSets::Logic));
+        app.add_system(clear_previews.in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn clear_previews(
+    mut commands: Commands,
+    q_preview: Query<Entity, Or<(With<BeamPreview>, With<TrajectoryDot>)>>,
+) {
+    for entity in &q_preview {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn toggle_trajectory(keys: Res<Input<KeyCode>>, mut overlay: ResMut<TrajectoryOverlay>) {
+    if keys.just_pressed(KeyCode::T) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_trajectory(
+    mut commands: Commands,
+    overlay: Res<TrajectoryOverlay>,
+    game_state: Res<GameState>,
+    current: Res<CurrentLevel>,
+    grid: Res<LevelGrid>,
+    q_ray: Query<&Ray>,
+    q_well: Query<&GravityWell>,
+    q_changed_ray: Query<(), Changed<Ray>>,
+    mut removed_rays: RemovedComponents<Ray>,
+    q_player: Query<(&Player, &FleetMember)>,
+    q_dot: Query<Entity, With<TrajectoryDot>>,
+    mut drawn_for: Local<Vec<(i32, i32)>>,
+) {
+    let rays_changed = !q_changed_ray.is_empty() || removed_rays.iter().next().is_some();
+    let player_tiles: Vec<(i32, i32)> = q_player.iter().map(|(player, _)| player.tile()).collect();
+    if !rays_changed && !overlay.is_changed() && *drawn_for == player_tiles {
+        return;
+    }
+    *drawn_for = player_tiles;
+
+    for entity in &q_dot {
+        commands.entity(entity).despawn();
+    }
+    if !overlay.enabled {
+        return;
+    }
+
+    let rays: Vec<&Ray> = q_ray.iter().collect();
+    let wells: Vec<GravityWell> = q_well.iter().cloned().collect();
+    let fleet = fleet_order(q_player.iter());
+    let paths = predict_paths(
+        &fleet,
+        &rays,
+        current.ship_mode,
+        &grid,
+        &wells,
+        game_state.tick,
+        overlay.tiles_ahead,
+    );
+    for point in paths.into_iter().flatten() {
+        commands.spawn((
+            path_dot(point, TRAJECTORY_COLOR, TRAJECTORY_DOT_SIZE),
+            TrajectoryDot,
+        ));
+    }
+}
+
+fn path_dot(point: Vec2, color: Color, size: f32) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::splat(size)),
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(point.x * TILE_SIZE, -point.y * TILE_SIZE, 150.),
+        ..Default::default()
+    }
+}
+
+// what the preview was last drawn for, it is only rebuilt when this changes
+#[derive(Default, PartialEq)]
+struct PreviewKey {
+    caster: Option<Entity>,
+    ray_count: i32,
+    player_tiles: Vec<(i32, i32)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_beam_preview(
+    mut commands: Commands,
+    pick_state: Res<PickState>,
+    game_state: Res<GameState>,
+    current: Res<CurrentLevel>,
+    grid: Res<LevelGrid>,
+    priority: Res<RayPriority>,
+    game_assets: Res<GameAssets>,
+    q_caster: Query<(
+        &RayCaster,
+        Option<&MovingCaster>,
+        Option<&BeamTiming>,
+        &LevelEntity,
+    )>,
+    q_ray: Query<&Ray>,
+    q_well: Query<&GravityWell>,
+    q_player: Query<(&Player, &FleetMember)>,
+    q_preview: Query<Entity, With<BeamPreview>>,
+    mut drawn: Local<PreviewKey>,
+) {
+    let caster = pick_state
+        .previewed()
+        .filter(|entity| q_caster.contains(*entity));
+    let player_tiles: Vec<(i32, i32)> = q_player.iter().map(|(player, _)| player.tile()).collect();
+    let key = PreviewKey {
+        caster,
+        ray_count: game_state.ray_count,
+        player_tiles,
+    };
+    // moving casters change the grid, their beams and the ghost with it
+    if *drawn == key && !grid.is_changed() {
+        return;
+    }
+    *drawn = key;
+
+    for entity in &q_preview {
+        commands.entity(entity).despawn();
+    }
+    let Some((ray_caster, moving, timing, level_entity)) =
+        caster.and_then(|entity| q_caster.get(entity).ok())
+    else {
+        return;
+    };
+    // a click turns these casters before they fire
+    let mut ray_caster = ray_caster.clone();
+    if moving.is_some_and(MovingCaster::turns_on_click) {
+        ray_caster.dir = ray_caster.dir.turned(1);
+    }
+
+    // the ghost gets the prio a click would give it, so it layers like the real beam
+    let ghost = fired_ray(
+        &ray_caster,
+        &level_entity.0,
+        timing,
+        &grid,
+        &priority,
+        game_state.ray_count,
+        game_state.tick,
+    );
+    let color = ray_color(ghost.prio).with_a(PREVIEW_ALPHA);
+    for sprite in spawn_ray_sprites(&ghost, color, &game_assets, &mut commands) {
+        commands.entity(sprite).insert(BeamPreview);
+    }
+
+    let mut rays: Vec<&Ray> = q_ray.iter().collect();
+    rays.push(&ghost);
+    let wells: Vec<GravityWell> = q_well.iter().cloned().collect();
+    let fleet = fleet_order(q_player.iter());
+    let paths = predict_paths(
+        &fleet,
+        &rays,
+        current.ship_mode,
+        &grid,
+        &wells,
+        game_state.tick,
+        PREVIEW_PATH_TILES,
+    );
+    for point in paths.into_iter().flatten() {
+        commands.spawn((path_dot(point, color, PREVIEW_DOT_SIZE), BeamPreview));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::level::Level;
+
+// prio distance between two layers, the rays of one layer stack inside it
+const LAYER_SPAN: i32 = 10_000;
+// rays of prio 0 are drawn at this z, below tiles and the ship
+const RAY_Z: f32 = 50.;
+const RAY_Z_STEP: f32 = 0.0001;
+
+// How overlapping rays are layered in a level, picked with the LDtk level
+// field `ray_order`. Every ray gets a prio from it and the smallest prio is on
+// top: it is drawn in front of the others and it is the ray the ship follows.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum RayOrder {
+    // rays fired earlier stay on top of later ones
+    #[default]
+    OldestOnTop,
+    // every ray fired covers all rays before it
+    NewestOnTop,
+    // fired rays sit on the layer of their caster, set by a Caster_layer
+    // entity on its tile, authored rays on the layer of their priority.
+    // Smaller layers are on top, within a layer older rays stay on top
+    Layers,
+}
+
+impl RayOrder {
+    pub fn from_level(level: &Level) -> Self {
+        match level.field("ray_order").and_then(|value| value.as_str()) {
+            Some("Newest_on_top") => RayOrder::NewestOnTop,
+            Some("Layers") => RayOrder::Layers,
+            _ => RayOrder::OldestOnTop,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct LevelPriority {
+    order: RayOrder,
+    // caster layers keyed by world tile, casters without one are on layer 0
+    caster_layers: HashMap<(i32, i32), i32>,
+}
+
+impl LevelPriority {
+    fn new(level: &Level) -> Self {
+        let rect = level.tile_rect();
+        let caster_layers = level
+            .entities("Caster_layer")
+            .map(|entity| {
+                let layer = entity
+                    .field("layer")
+                    .and_then(|value| value.as_i64())
+                    .unwrap_or_default();
+                (
+                    (rect.x + entity.grid[0], rect.y + entity.grid[1]),
+                    layer as i32,
+                )
+            })
+            .collect();
+        LevelPriority {
+            order: RayOrder::from_level(level),
+            caster_layers,
+        }
+    }
+}
+
+// Resources
+
+// the ray order of every streamed in level, keyed by level iid
+#[derive(Resource, Default, Clone)]
+pub struct RayPriority {
+    levels: HashMap<String, LevelPriority>,
+}
+
+impl RayPriority {
+    pub fn new<'a>(levels: impl IntoIterator<Item = &'a Level>) -> Self {
+        RayPriority {
+            levels: levels
+                .into_iter()
+                .map(|level| (level.iid.clone(), LevelPriority::new(level)))
+                .collect(),
+        }
+    }
+
+    pub fn order(&self, iid: &str) -> RayOrder {
+        self.levels
+            .get(iid)
+            .map(|level| level.order)
+            .unwrap_or_default()
+    }
+
+    // prio of a Lightray authored with `priority`, spawned after `count` other rays
+    pub fn authored(&self, iid: &str, priority: i32, count: i32) -> i32 {
+        match self.order(iid) {
+            RayOrder::Layers => priority * LAYER_SPAN + count,
+            RayOrder::OldestOnTop | RayOrder::NewestOnTop => priority,
+        }
+    }
+
+    // prio of the ray the caster at world tile x, y fires after `count` other rays
+    pub fn fired(&self, iid: &str, x: i32, y: i32, count: i32) -> i32 {
+        let Some(level) = self.levels.get(iid) else {
+            return count;
+        };
+        match level.order {
+            RayOrder::OldestOnTop => count,
+            RayOrder::NewestOnTop => -count,
+            RayOrder::Layers => {
+                let layer = level
+                    .caster_layers
+                    .get(&(x, y))
+                    .copied()
+                    .unwrap_or_default();
+                layer * LAYER_SPAN + count
+            }
+        }
+    }
+}
+
+// sprite z of a ray, rays with a smaller prio are drawn in front
+pub fn ray_z(prio: i32) -> f32 {
+    RAY_Z - prio as f32 * RAY_Z_STEP
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    level::{EntityInstance, Level},
+    seconds_to_ticks, Ray,
+};
+
+// photons fade in and out over 1 / FADE_SPEED seconds
+const FADE_SPEED: f32 = 8.;
+
+// When a ray is lit, authored in seconds with the fields `duration`, `period`,
+// `lit_for` and `phase`. Lightray entities carry them for themselves and
+// Caster_pulse entities for the beams of the caster on their tile. Rays only
+// carry ships while lit, see movement.rs.
+#[derive(Component, Clone, Default, Debug, PartialEq)]
+pub struct BeamTiming {
+    // ticks from the ray's first tick until it goes out for good
+    pub duration: Option<u32>,
+    pub pulse: Option<Pulse>,
+}
+
+// lit for the first `lit_for` ticks of every `period`, counted in level ticks
+// shifted by `phase`, so beams fired at different times pulse in step
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pulse {
+    pub period: u32,
+    pub lit_for: u32,
+    pub phase: u32,
+}
+
+impl BeamTiming {
+    pub fn from_entity(entity: &EntityInstance) -> Self {
+        let seconds = |identifier| {
+            entity
+                .field(identifier)
+                .and_then(|value| value.as_f64())
+                .map(seconds_to_ticks)
+        };
+        let pulse = seconds("period")
+            .filter(|period| *period > 0)
+            .map(|period| Pulse {
+                period,
+                lit_for: seconds("lit_for").unwrap_or(period / 2),
+                phase: seconds("phase").unwrap_or_default() % period,
+            });
+        BeamTiming {
+            duration: seconds("duration"),
+            pulse,
+        }
+    }
+
+    // `born` is the first tick of the ray
+    pub fn lit(&self, tick: u32, born: u32) -> bool {
+        let alive = self
+            .duration
+            .is_none_or(|duration| tick.saturating_sub(born) < duration);
+        let on = self
+            .pulse
+            .as_ref()
+            .is_none_or(|pulse| (tick + pulse.phase) % pulse.period < pulse.lit_for);
+        alive && on
+    }
+}
+
+// Caster_pulse entities of a level, keyed by the world tile of their caster
+pub fn caster_timings(level: &Level) -> HashMap<(i32, i32), BeamTiming> {
+    let rect = level.tile_rect();
+    level
+        .entities("Caster_pulse")
+        .map(|entity| {
+            (
+                (rect.x + entity.grid[0], rect.y + entity.grid[1]),
+                BeamTiming::from_entity(entity),
+            )
+        })
+        .collect()
+}
+
+impl Ray {
+    pub fn timed(self, timing: BeamTiming, born: u32) -> Self {
+        Ray {
+            timing,
+            born,
+            ..self
+        }
+    }
+
+    pub fn lit(&self, tick: u32) -> bool {
+        !self.switched_off && self.timing.lit(tick, self.born)
+    }
+
+    // level ticks start over from 0 at `tick`, shifts the ray so it goes out
+    // and pulses exactly as it would have without the restart
+    pub fn rebase(&mut self, tick: u32) {
+        let age = tick.saturating_sub(self.born);
+        self.born = self.born.saturating_sub(tick);
+        if let Some(duration) = &mut self.timing.duration {
+            *duration = duration.saturating_sub(age);
+        }
+        if let Some(pulse) = &mut self.timing.pulse {
+            pulse.phase = (pulse.phase + tick % pulse.period) % pulse.period;
+        }
+    }
+}
+
+// alpha of a photon sprite one frame closer to whether its ray is lit
+pub fn fade(alpha: f32, lit: bool, delta: f32) -> f32 {
+    let target = if lit { 1. } else { 0. };
+    let step = delta * FADE_SPEED;
+    alpha + (target - alpha).clamp(-step, step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulse(period: u32, lit_for: u32, phase: u32) -> BeamTiming {
+        BeamTiming {
+            duration: None,
+            pulse: Some(Pulse {
+                period,
+                lit_for,
+                phase,
+            }),
+        }
+    }
+
+    #[test]
+    fn untimed_rays_are_always_lit() {
+        let timing = BeamTiming::default();
+        assert!((0..1000).all(|tick| timing.lit(tick, 0)));
+    }
+
+    #[test]
+    fn rays_go_out_after_their_duration() {
+        let timing = BeamTiming {
+            duration: Some(30),
+            pulse: None,
+        };
+        assert!(timing.lit(40, 20));
+        assert!(timing.lit(49, 20));
+        assert!(!timing.lit(50, 20));
+    }
+
+    #[test]
+    fn pulses_follow_level_ticks_shifted_by_phase() {
+        let timing = pulse(60, 20, 0);
+        let lit: Vec<bool> = [0, 19, 20, 59, 60].map(|tick| timing.lit(tick, 0)).into();
+        assert_eq!(lit, vec![true, true, false, false, true]);
+        // a ray fired late pulses in step with the others
+        assert!(timing.lit(125, 100));
+        assert!(!timing.lit(145, 100));
+
+        let shifted = pulse(60, 20, 10);
+        assert!(!shifted.lit(10, 0));
+        assert!(shifted.lit(50, 0));
+    }
+
+    #[test]
+    fn rebased_rays_stay_lit_as_before() {
+        let timings = [
+            BeamTiming {
+                duration: Some(30),
+                pulse: None,
+            },
+            pulse(60, 20, 10),
+            BeamTiming {
+                duration: Some(200),
+                pulse: Some(Pulse {
+                    period: 45,
+                    lit_for: 30,
+                    phase: 44,
+                }),
+            },
+        ];
+        for timing in timings {
+            for born in [0, 20, 100] {
+                let ray = Ray {
+                    src_x: 0,
+                    src_y: 0,
+                    dest_x: 3,
+                    dest_y: 0,
+                    reversed: false,
+                    horizontal: true,
+                    prio: 0,
+                    timing: timing.clone(),
+                    born,
+                    intensity: 1.,
+                    switched_off: false,
+                };
+                let mut rebased = ray.clone();
+                rebased.rebase(70);
+                for tick in 0..300 {
+                    assert_eq!(
+                        rebased.lit(tick),
+                        ray.lit(tick + 70),
+                        "{:?} {}",
+                        timing,
+                        born
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fading_moves_towards_the_lit_state() {
+        assert_eq!(fade(1., false, 0.0625), 0.5);
+        assert_eq!(fade(0.1, true, 1.), 1.);
+        assert_eq!(fade(1., true, 0.0625), 1.);
+    }
+}
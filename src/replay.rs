@@ -0,0 +1,729 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::FileAssetIo, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activate_caster,
+    caster::{caster_motions, click_caster, place_caster, MovingCaster},
+    caster_dir, fired_ray,
+    fleet::{fleet, step_fleet, FleetMember},
+    hazards::{asteroids, gravity_wells, place_asteroid, ship_lost, Asteroid, GravityWell},
+    level::{read_project_levels, Level},
+    level_rays,
+    loading::{GameAssets, PROJECT_PATH},
+    physics::ShipMode,
+    picking::Pickable,
+    priority::RayPriority,
+    pulse::{caster_timings, BeamTiming},
+    retrace_ray,
+    save::save_dir,
+    triggers::{home_tile, remote_fires, trigger_hits, TriggerNetwork},
+    update_hover_tint,
+    world::{CurrentLevel, LevelEntity, SelectedLevel, WorldLevels},
+    AppState, CasterActivated, GameState, GameSystemSets, LevelGrid, LevelSolved, Player, Ray,
+    RayCaster, ShipLost, SimulationSets, SIM_STEP,
+};
+
+// bumped whenever the simulation changes in a way that plays old replays out differently
+const REPLAY_VERSION: u32 = 2;
+const REPLAY_DIR: &str = "replays";
+const REPLAY_EXTENSION: &str = "replay";
+// ticks the ship gets after the last activation to reach the goal
+const MAX_IDLE_TICKS: u32 = 60 * 60;
+
+// Replay data
+
+// A caster firing, stored as [tick, x, y] to keep replays small. The caster
+// position is level local so replays survive moving levels in the LDtk world.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(from = "(u32, i32, i32)", into = "(u32, i32, i32)")]
+pub struct Activation {
+    pub tick: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl From<(u32, i32, i32)> for Activation {
+    fn from((tick, x, y): (u32, i32, i32)) -> Self {
+        Activation { tick, x, y }
+    }
+}
+
+impl From<Activation> for (u32, i32, i32) {
+    fn from(activation: Activation) -> Self {
+        (activation.tick, activation.x, activation.y)
+    }
+}
+
+// The simulation has no randomness, so the level and the activations fully
+// determine a session. The version pins down the rules it was recorded with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub version: u32,
+    // iid of the level the session started in
+    pub level: String,
+    // sorted by tick
+    pub activations: Vec<Activation>,
+}
+
+impl Replay {
+    pub fn new(level: &str) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            level: level.to_string(),
+            activations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnknownVersion(u32),
+    UnknownLevel(String),
+    // an activation points at a tile without a caster
+    NoCaster(Activation),
+    // the ship was still not at the goal after this many ticks
+    GoalNotReached(u32),
+    // a hazard destroyed the ship on this tick
+    ShipLost(u32),
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(error: serde_json::Error) -> Self {
+        ReplayError::Json(error)
+    }
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Json(error) => write!(f, "invalid replay: {}", error),
+            ReplayError::UnknownVersion(version) => write!(
+                f,
+                "replay version {} does not match {}",
+                version, REPLAY_VERSION
+            ),
+            ReplayError::UnknownLevel(iid) => write!(f, "level {} is not in the project", iid),
+            ReplayError::NoCaster(activation) => write!(
+                f,
+                "tick {} activates {}, {} which is not a caster",
+                activation.tick, activation.x, activation.y
+            ),
+            ReplayError::GoalNotReached(ticks) => {
+                write!(f, "the ship did not reach the goal within {} ticks", ticks)
+            }
+            ReplayError::ShipLost(tick) => write!(f, "the ship was lost on tick {}", tick),
+        }
+    }
+}
+
+pub fn read_replay(path: &Path) -> Result<Replay, ReplayError> {
+    let replay: Replay = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if replay.version != REPLAY_VERSION {
+        return Err(ReplayError::UnknownVersion(replay.version));
+    }
+    Ok(replay)
+}
+
+pub fn write_replay(path: &Path, replay: &Replay) -> Result<(), ReplayError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(replay)?)?;
+    Ok(())
+}
+
+// Verification
+
+// How a simulation tick ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Running,
+    // every ship of the fleet reached its goal
+    Solved,
+    // a hazard destroyed a ship
+    Lost,
+}
+
+// The game's simulation without any ECS, one tick at a time. It only keeps
+// the state; every rule is a plain function the game's systems call as well,
+// from beam tracing and firing to ship steps, triggers and hazards, so the two
+// cannot drift apart. Cloning it forks the session, which the solver uses to
+// try out clicks.
+#[derive(Clone)]
+pub struct Simulation<'a> {
+    level: &'a Level,
+    // the level and its neighbours, streamed in like the game does when the session starts
+    streamed: Vec<&'a Level>,
+    grid: LevelGrid,
+    priority: RayPriority,
+    authored: Vec<Ray>,
+    ray_count: i32,
+    casters: Vec<(RayCaster, MovingCaster)>,
+    timings: HashMap<(i32, i32), BeamTiming>,
+    asteroids: Vec<Asteroid>,
+    network: TriggerNetwork,
+    wells: Vec<GravityWell>,
+    // fired beams, re-traced every tick as casters and asteroids move
+    beams: Vec<(BeamSource, Ray)>,
+    ships: Vec<Player>,
+    members: Vec<FleetMember>,
+    arrived: Vec<bool>,
+    mode: ShipMode,
+    tick: u32,
+}
+
+impl<'a> Simulation<'a> {
+    // the session as it starts in `level`, `levels` are the ones it may stream in
+    pub fn new(level: &'a Level, levels: &'a [Level]) -> Self {
+        let by_iid = |iid: &str| levels.iter().find(|level| level.iid == iid);
+        let streamed: Vec<&Level> = std::iter::once(level)
+            .chain(
+                level
+                    .neighbours
+                    .iter()
+                    .filter_map(|neighbour| by_iid(&neighbour.level_iid)),
+            )
+            .collect();
+        let grid = LevelGrid::new(streamed.iter().copied());
+        let priority = RayPriority::new(streamed.iter().copied());
+        let mut authored = Vec::new();
+        for level in &streamed {
+            let count = authored.len() as i32;
+            authored.extend(level_rays(level, &priority, count));
+        }
+        let ray_count = authored.len() as i32;
+        let casters = streamed
+            .iter()
+            .flat_map(|level| caster_motions(level))
+            .filter_map(|((x, y), motion)| {
+                let dir = caster_dir(grid.get(x, y))?;
+                let moving = MovingCaster::new(motion, dir);
+                Some((moving.pose(0), moving))
+            })
+            .collect();
+        let timings = streamed
+            .iter()
+            .flat_map(|level| caster_timings(level))
+            .collect();
+        let asteroids = streamed.iter().flat_map(|level| asteroids(level)).collect();
+        let network = TriggerNetwork::new(streamed.iter().copied());
+        let wells = streamed
+            .iter()
+            .flat_map(|level| gravity_wells(level))
+            .collect();
+
+        let (starts, members): (Vec<_>, Vec<_>) = fleet(level).into_iter().unzip();
+        let ships: Vec<Player> = starts
+            .into_iter()
+            .map(|(x, y)| Player {
+                x,
+                y,
+                direction: None,
+                velocity: Vec2::ZERO,
+            })
+            .collect();
+        Simulation {
+            level,
+            streamed,
+            grid,
+            priority,
+            authored,
+            ray_count,
+            casters,
+            timings,
+            asteroids,
+            network,
+            wells,
+            beams: Vec::new(),
+            arrived: vec![false; ships.len()],
+            ships,
+            members,
+            mode: ShipMode::from_level(level),
+            tick: 0,
+        }
+    }
+
+    // the tick `step` simulates next
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    // fires the caster on level local tile x, y on the coming tick, false
+    // when there is no caster
+    pub fn activate(&mut self, x: i32, y: i32) -> bool {
+        let rect = self.level.tile_rect();
+        let (x, y) = (rect.x + x, rect.y + y);
+        let moving = self
+            .casters
+            .iter()
+            .position(|(caster, _)| (caster.pos_x, caster.pos_y) == (x, y));
+        // timings stay with moving casters, keyed by the tile they start on
+        let (source, timing) = match moving {
+            Some(index) => {
+                let (caster, moving) = &mut self.casters[index];
+                let start = moving.pose(0);
+                click_caster(caster, Some(moving), &mut self.grid);
+                (
+                    BeamSource::Moving(index),
+                    self.timings.get(&(start.pos_x, start.pos_y)),
+                )
+            }
+            None => {
+                let Some(dir) = caster_dir(self.grid.get(x, y)) else {
+                    return false;
+                };
+                let caster = RayCaster {
+                    dir,
+                    pos_x: x,
+                    pos_y: y,
+                };
+                (BeamSource::Fixed(caster), self.timings.get(&(x, y)))
+            }
+        };
+        let ray = fired_ray(
+            source.caster(&self.casters),
+            &self.level.iid,
+            timing,
+            &self.grid,
+            &self.priority,
+            self.ray_count,
+            self.tick,
+        );
+        self.beams.push((source, ray));
+        self.ray_count += 1;
+        true
+    }
+
+    // runs one tick in the order of the game's systems
+    pub fn step(&mut self) -> Outcome {
+        let tick = self.tick;
+        self.tick += 1;
+        for asteroid in &mut self.asteroids {
+            let tile = asteroid.rail.at(tick);
+            place_asteroid(asteroid, tile, &mut self.grid);
+        }
+        for (caster, moving) in &mut self.casters {
+            let pose = moving.pose(tick);
+            place_caster(caster, pose, &mut self.grid);
+        }
+        let casters = &self.casters;
+        let mut fired: Vec<Ray> = self
+            .beams
+            .iter()
+            .map(|(source, ray)| retrace_ray(ray, source.caster(casters), &self.grid))
+            .collect();
+
+        // triggers see the beams as traced above, like update_triggers
+        let rays = self.authored.iter().map(|ray| (ray, None)).chain(
+            self.beams
+                .iter()
+                .zip(&fired)
+                .map(|((source, _), ray)| (ray, Some(source.caster(casters)))),
+        );
+        let hits = trigger_hits(rays, tick);
+        let changes = self.network.update(&hits);
+        if !changes.is_empty() {
+            self.network.place_doors(&mut self.grid);
+        }
+        for ((source, beam), ray) in self.beams.iter_mut().zip(&mut fired) {
+            let off = self.network.switched_off(source.home(casters));
+            beam.switched_off = off;
+            ray.switched_off = off;
+        }
+
+        let rays: Vec<Ray> = self.authored.iter().cloned().chain(fired).collect();
+        let wells = &self.wells;
+        step_fleet(
+            &mut self.ships,
+            &rays,
+            self.mode,
+            &self.grid,
+            wells,
+            tick,
+            SIM_STEP,
+        );
+        let rect = self.level.tile_rect();
+        for ((ship, member), arrived) in self.ships.iter().zip(&self.members).zip(&mut self.arrived)
+        {
+            *arrived |= member.on_goal(ship, &self.grid, rect);
+        }
+        if self.arrived.iter().all(|arrived| *arrived) {
+            return Outcome::Solved;
+        }
+        if self.ships.iter().any(|ship| ship_lost(ship, &self.grid)) {
+            return Outcome::Lost;
+        }
+
+        // remote casters fire like fire_remote_casters
+        for ((x, y), born) in remote_fires(&changes, tick) {
+            let source = match self
+                .casters
+                .iter()
+                .position(|(_, moving)| moving.start_tile() == (x, y))
+            {
+                Some(index) => BeamSource::Moving(index),
+                None => {
+                    let Some(dir) = caster_dir(self.grid.get(x, y)) else {
+                        continue;
+                    };
+                    BeamSource::Fixed(RayCaster {
+                        dir,
+                        pos_x: x,
+                        pos_y: y,
+                    })
+                }
+            };
+            let iid = self
+                .streamed
+                .iter()
+                .find(|level| level.tile_rect().contains(x, y))
+                .map_or("", |level| level.iid.as_str());
+            let ray = fired_ray(
+                source.caster(&self.casters),
+                iid,
+                self.timings.get(&(x, y)),
+                &self.grid,
+                &self.priority,
+                self.ray_count,
+                born,
+            );
+            self.beams.push((source, ray));
+            self.ray_count += 1;
+        }
+        Outcome::Running
+    }
+
+    // no ship moves, so nothing changes until the next click unless casters,
+    // asteroids or pulses move on their own
+    pub fn at_rest(&self) -> bool {
+        self.ships
+            .iter()
+            .all(|ship| ship.direction.is_none() && ship.velocity == Vec2::ZERO)
+    }
+
+    // what decides how a session at rest plays on: where the ships are, which
+    // arrived, the grid with its doors and the fired casters from the top ray
+    // down, as a ray under one from the same caster never shows
+    pub fn rest_key(&self) -> Vec<i32> {
+        let mut beams: Vec<(i32, (i32, i32))> = self
+            .beams
+            .iter()
+            .map(|(source, ray)| (ray.prio, source.home(&self.casters)))
+            .collect();
+        beams.sort();
+        let mut key: Vec<i32> = self
+            .ships
+            .iter()
+            .flat_map(|ship| [ship.x.to_bits() as i32, ship.y.to_bits() as i32])
+            .chain(self.arrived.iter().map(|arrived| *arrived as i32))
+            .chain(self.grid.tiles.iter().copied())
+            .collect();
+        let mut seen = Vec::new();
+        for (_, home) in beams {
+            if !seen.contains(&home) {
+                seen.push(home);
+                key.extend([home.0, home.1]);
+            }
+        }
+        key
+    }
+}
+
+// Plays a replay headless in a Simulation. Returns the number of ticks the
+// fleet needed to reach its goals in the replay's level.
+pub fn verify(replay: &Replay, levels: &[Level]) -> Result<u32, ReplayError> {
+    let level = levels
+        .iter()
+        .find(|level| level.iid == replay.level)
+        .ok_or_else(|| ReplayError::UnknownLevel(replay.level.clone()))?;
+    let mut simulation = Simulation::new(level, levels);
+    let last_tick = replay
+        .activations
+        .last()
+        .map_or(0, |activation| activation.tick);
+    let mut activations = replay.activations.iter().peekable();
+    for tick in 0..last_tick + MAX_IDLE_TICKS {
+        while let Some(activation) = activations.next_if(|activation| activation.tick <= tick) {
+            if !simulation.activate(activation.x, activation.y) {
+                return Err(ReplayError::NoCaster(*activation));
+            }
+        }
+        match simulation.step() {
+            Outcome::Running => (),
+            Outcome::Solved => return Ok(tick + 1),
+            Outcome::Lost => return Err(ReplayError::ShipLost(tick)),
+        }
+    }
+    Err(ReplayError::GoalNotReached(last_tick + MAX_IDLE_TICKS))
+}
+
+// the caster a beam in a Simulation is traced from
+#[derive(Clone)]
+enum BeamSource {
+    Fixed(RayCaster),
+    // index into the moving casters
+    Moving(usize),
+}
+
+impl BeamSource {
+    fn caster<'a>(&'a self, casters: &'a [(RayCaster, MovingCaster)]) -> &'a RayCaster {
+        match self {
+            BeamSource::Fixed(caster) => caster,
+            BeamSource::Moving(index) => &casters[*index].0,
+        }
+    }
+
+    // the tile the caster starts on, see home_tile
+    fn home(&self, casters: &[(RayCaster, MovingCaster)]) -> (i32, i32) {
+        match self {
+            BeamSource::Fixed(caster) => home_tile(caster, None),
+            BeamSource::Moving(index) => home_tile(&casters[*index].0, Some(&casters[*index].1)),
+        }
+    }
+}
+
+// `--verify <file>`, prints the outcome and returns the exit code
+pub fn verify_command(path: &Path) -> i32 {
+    let project = FileAssetIo::get_base_path()
+        .join("assets")
+        .join(PROJECT_PATH);
+    let levels = match read_project_levels(&project) {
+        Ok(levels) => levels,
+        Err(error) => {
+            eprintln!("could not read '{}': {}", project.display(), error);
+            return 2;
+        }
+    };
+    match read_replay(path).and_then(|replay| verify(&replay, &levels)) {
+        Ok(ticks) => {
+            println!(
+                "{}: goal reached after {} ticks ({:.2}s)",
+                path.display(),
+                ticks,
+                ticks as f32 * SIM_STEP
+            );
+            0
+        }
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            1
+        }
+    }
+}
+
+// Resources
+
+#[derive(Resource, Default)]
+enum Recording {
+    #[default]
+    Idle,
+    Active(Replay),
+    // the ship left the level it started in or already solved it
+    Finished,
+}
+
+#[derive(Resource)]
+struct Playback {
+    replay: Replay,
+    // index of the next activation to apply
+    next: usize,
+    started: bool,
+}
+
+// Plugin
+
+pub struct ReplayPlugin {
+    // replay to play instead of taking player input
+    pub playback: Option<PathBuf>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recording>();
+        app.add_systems(
+            (
+                restart_recording,
+                record_activations.after(update_hover_tint),
+                store_replay,
+            )
+                .chain()
+                .in_set(GameSystemSets::Logic),
+        );
+        app.add_system(reset_recording.in_schedule(OnExit(AppState::InGame)));
+
+        let Some(path) = &self.playback else {
+            return;
+        };
+        match read_replay(path) {
+            Ok(replay) => {
+                app.insert_resource(Playback {
+                    replay,
+                    next: 0,
+                    started: false,
+                });
+                app.add_system(start_playback.in_set(OnUpdate(AppState::LevelSelect)));
+                app.add_system(lock_casters.in_set(GameSystemSets::Input));
+                app.add_system(
+                    drive_playback
+                        .in_set(SimulationSets::Input)
+                        .in_schedule(CoreSchedule::FixedUpdate),
+                );
+            }
+            Err(error) => error!("could not read replay '{}': {}", path.display(), error),
+        }
+    }
+}
+
+fn record_activations(
+    current: Res<CurrentLevel>,
+    mut activated: EventReader<CasterActivated>,
+    mut recording: ResMut<Recording>,
+) {
+    // sessions start where start_world put the ship, on the first frame in game
+    if matches!(*recording, Recording::Idle) && !current.iid.is_empty() {
+        *recording = Recording::Active(Replay::new(&current.iid));
+    }
+    if matches!(&*recording, Recording::Active(replay) if replay.level != current.iid) {
+        *recording = Recording::Finished;
+    }
+    let Recording::Active(replay) = &mut *recording else {
+        activated.clear();
+        return;
+    };
+    for event in activated.iter() {
+        replay.activations.push(Activation {
+            tick: event.tick,
+            x: event.x - current.rect.x,
+            y: event.y - current.rect.y,
+        });
+    }
+}
+
+fn store_replay(
+    mut solved: EventReader<LevelSolved>,
+    world_levels: WorldLevels,
+    mut recording: ResMut<Recording>,
+) {
+    for event in solved.iter() {
+        let Recording::Active(replay) = &*recording else {
+            continue;
+        };
+        if replay.level != event.iid {
+            continue;
+        }
+        let Some(level) = world_levels.by_iid(&replay.level) else {
+            continue;
+        };
+        if let Some(dir) = save_dir() {
+            let path = dir
+                .join(REPLAY_DIR)
+                .join(&level.identifier)
+                .with_extension(REPLAY_EXTENSION);
+            match write_replay(&path, replay) {
+                Ok(()) => info!("replay saved to '{}'", path.display()),
+                Err(error) => error!("could not write replay '{}': {}", path.display(), error),
+            }
+        }
+        *recording = Recording::Finished;
+    }
+}
+
+fn reset_recording(mut recording: ResMut<Recording>) {
+    *recording = Recording::Idle;
+}
+
+// the level starts over when the ship is lost, and so does its replay
+fn restart_recording(mut lost: EventReader<ShipLost>, mut recording: ResMut<Recording>) {
+    if lost.iter().count() > 0 {
+        *recording = Recording::Idle;
+    }
+}
+
+fn start_playback(
+    mut playback: ResMut<Playback>,
+    mut selected: ResMut<SelectedLevel>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if playback.started {
+        return;
+    }
+    playback.started = true;
+    selected.0 = Some(playback.replay.level.clone());
+    next_state.set(AppState::InGame);
+}
+
+// the replay is the only input while it plays
+fn lock_casters(mut q_pickable: Query<&mut Pickable, With<RayCaster>>) {
+    for mut pickable in &mut q_pickable {
+        pickable.enabled = false;
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn drive_playback(
+    mut playback: ResMut<Playback>,
+    current: Res<CurrentLevel>,
+    mut grid: ResMut<LevelGrid>,
+    priority: Res<RayPriority>,
+    game_assets: Res<GameAssets>,
+    mut game_state: ResMut<GameState>,
+    mut activated: EventWriter<CasterActivated>,
+    mut commands: Commands,
+    mut q_caster: Query<(
+        Entity,
+        &mut RayCaster,
+        Option<&mut MovingCaster>,
+        Option<&BeamTiming>,
+        &LevelEntity,
+    )>,
+) {
+    if current.iid != playback.replay.level {
+        return;
+    }
+    while let Some(activation) = playback.replay.activations.get(playback.next).copied() {
+        if activation.tick > game_state.tick {
+            break;
+        }
+        playback.next += 1;
+        let (x, y) = (current.rect.x + activation.x, current.rect.y + activation.y);
+        let caster = q_caster
+            .iter_mut()
+            .find(|(_, caster, _, _, _)| caster.pos_x == x && caster.pos_y == y);
+        match caster {
+            Some((entity, mut caster, moving, timing, level_entity)) => {
+                click_caster(
+                    &mut caster,
+                    moving.map(|moving| moving.into_inner()),
+                    &mut grid,
+                );
+                activated.send(activate_caster(
+                    &caster,
+                    entity,
+                    timing,
+                    level_entity,
+                    &grid,
+                    &priority,
+                    &game_assets,
+                    &mut game_state,
+                    &mut commands,
+                ));
+            }
+            None => warn!("{}", ReplayError::NoCaster(activation)),
+        }
+    }
+}
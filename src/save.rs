@@ -0,0 +1,467 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    camera::CameraControl, collectible::collectibles, preview::TrajectoryOverlay, score::Pars,
+    world::WorldLevels, GameSystemSets, LevelSolved,
+};
+
+const SAVE_VERSION: u32 = 1;
+const SAVE_FILE: &str = "save.json";
+const APP_DIR: &str = "sole";
+
+// Each entry upgrades a save from version `index + 1` to `index + 2` in place,
+// so a save of any older version runs through all the steps after it.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+// Save data
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    // keyed by LDtk level iid, which survives renaming levels
+    pub levels: HashMap<String, LevelProgress>,
+    pub settings: Settings,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            levels: HashMap::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct LevelProgress {
+    pub unlocked: bool,
+    pub solved: bool,
+    // fewest caster activations the level was solved with
+    pub best_clicks: Option<i32>,
+    // fastest run in seconds
+    pub best_time: Option<f32>,
+    // most stars any run earned
+    pub stars: u8,
+    // level local tiles of every collectible picked up on a run that reached the goal
+    pub collected: Vec<(i32, i32)>,
+    // a single run reached the goal with every collectible of the level
+    pub collected_all: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub show_trajectory: bool,
+    pub trajectory_tiles: f32,
+    pub camera_smoothing: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let overlay = TrajectoryOverlay::default();
+        Settings {
+            show_trajectory: overlay.enabled,
+            trajectory_tiles: overlay.tiles_ahead,
+            camera_smoothing: CameraControl::default().smoothing,
+        }
+    }
+}
+
+impl SaveData {
+    pub fn level(&self, iid: &str) -> LevelProgress {
+        self.levels.get(iid).cloned().unwrap_or_default()
+    }
+
+    pub fn record_solved(&mut self, iid: &str, clicks: i32, time: f32, stars: u8) {
+        let progress = self.levels.entry(iid.to_string()).or_default();
+        progress.unlocked = true;
+        progress.solved = true;
+        progress.best_clicks = Some(match progress.best_clicks {
+            Some(best) => best.min(clicks),
+            None => clicks,
+        });
+        progress.best_time = Some(match progress.best_time {
+            Some(best) => best.min(time),
+            None => time,
+        });
+        progress.stars = progress.stars.max(stars);
+    }
+
+    // `collected` by a run that reached the goal, out of `total` in the level
+    pub fn record_collected(&mut self, iid: &str, collected: &[(i32, i32)], total: usize) {
+        let progress = self.levels.entry(iid.to_string()).or_default();
+        for tile in collected {
+            if !progress.collected.contains(tile) {
+                progress.collected.push(*tile);
+            }
+        }
+        progress.collected.sort();
+        progress.collected_all |= total > 0 && collected.len() >= total;
+    }
+
+    pub fn unlock(&mut self, iid: &str) {
+        self.levels.entry(iid.to_string()).or_default().unlocked = true;
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    // written by a newer build, we refuse to guess what it means
+    UnknownVersion(u32),
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(error: serde_json::Error) -> Self {
+        SaveError::Json(error)
+    }
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "{}", error),
+            SaveError::Json(error) => write!(f, "invalid save data: {}", error),
+            SaveError::UnknownVersion(version) => {
+                write!(f, "save version {} is newer than {}", version, SAVE_VERSION)
+            }
+        }
+    }
+}
+
+// parses a save of any known version, upgrading older ones
+pub fn parse_save(json: &str) -> Result<SaveData, SaveError> {
+    upgrade_save(json, SAVE_VERSION, MIGRATIONS)
+}
+
+// parse_save for a build at save version `current`, with its `migrations`
+fn upgrade_save(
+    json: &str,
+    current: u32,
+    migrations: &[fn(&mut Value)],
+) -> Result<SaveData, SaveError> {
+    let mut value: Value = serde_json::from_str(json)?;
+    let version = value
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or(1) as u32;
+    if version > current {
+        return Err(SaveError::UnknownVersion(version));
+    }
+    for migration in &migrations[(version.max(1) - 1) as usize..] {
+        migration(&mut value);
+    }
+    value["version"] = current.into();
+    Ok(serde_json::from_value(value)?)
+}
+
+// Storage
+
+// platform config directory, None where there is no file system to write to
+pub fn save_dir() -> Option<PathBuf> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let env_dir = |name: &str| std::env::var_os(name).filter(|dir| !dir.is_empty());
+    let base = if cfg!(target_os = "windows") {
+        PathBuf::from(env_dir("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env_dir("HOME")?).join("Library/Application Support")
+    } else {
+        match env_dir("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env_dir("HOME")?).join(".config"),
+        }
+    };
+    Some(base.join(APP_DIR))
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+// Loads the save, falling back to the backup of the previous save and finally
+// to a fresh save. Unreadable files are moved aside instead of overwritten,
+// saves from a newer build are left alone and reported as an error.
+pub fn load_save(path: &Path) -> Result<SaveData, SaveError> {
+    let backup = with_extension(path, "bak");
+    for candidate in [path, backup.as_path()] {
+        let json = match fs::read_to_string(candidate) {
+            Ok(json) => json,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => {
+                warn!("could not read save '{}': {}", candidate.display(), error);
+                continue;
+            }
+        };
+        match parse_save(&json) {
+            Ok(save) => return Ok(save),
+            Err(SaveError::UnknownVersion(version)) => {
+                return Err(SaveError::UnknownVersion(version))
+            }
+            Err(error) => {
+                let corrupt = with_extension(candidate, "corrupt");
+                warn!(
+                    "save '{}' is unusable ({}), moving it to '{}'",
+                    candidate.display(),
+                    error,
+                    corrupt.display()
+                );
+                let _ = fs::rename(candidate, corrupt);
+            }
+        }
+    }
+    Ok(SaveData::default())
+}
+
+// writes to a temporary file first so a crash never leaves half a save behind
+pub fn write_save(path: &Path, save: &SaveData) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = with_extension(path, "tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(save)?)?;
+    if path.exists() {
+        fs::rename(path, with_extension(path, "bak"))?;
+    }
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+// Resources
+
+#[derive(Resource)]
+pub struct SaveGame {
+    pub data: SaveData,
+    // None keeps progress in memory only
+    path: Option<PathBuf>,
+}
+
+impl SaveGame {
+    pub fn store(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(error) = write_save(path, &self.data) {
+            error!("could not write save '{}': {}", path.display(), error);
+        }
+    }
+}
+
+// Plugin
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let mut path = save_dir().map(|dir| dir.join(SAVE_FILE));
+        let data = match path.as_deref().map(load_save) {
+            Some(Ok(data)) => data,
+            Some(Err(error)) => {
+                warn!("{}, progress will not be saved", error);
+                path = None;
+                SaveData::default()
+            }
+            None => SaveData::default(),
+        };
+        app.insert_resource(TrajectoryOverlay {
+            enabled: data.settings.show_trajectory,
+            tiles_ahead: data.settings.trajectory_tiles,
+        });
+        app.insert_resource(CameraControl {
+            smoothing: data.settings.camera_smoothing,
+            ..Default::default()
+        });
+        app.insert_resource(SaveGame { data, path });
+        app.add_system(record_solved.in_set(GameSystemSets::Logic));
+        app.add_system(store_settings);
+    }
+}
+
+fn record_solved(
+    mut solved: EventReader<LevelSolved>,
+    world_levels: WorldLevels,
+    pars: Pars,
+    mut save: ResMut<SaveGame>,
+) {
+    let mut changed = false;
+    for event in solved.iter() {
+        let stars = pars.get(&event.iid).stars(event.clicks, event.time);
+        save.data
+            .record_solved(&event.iid, event.clicks, event.time, stars);
+        // solving a level opens the way to its neighbours
+        if let Some(level) = world_levels.by_iid(&event.iid) {
+            let total = collectibles(level).len();
+            save.data
+                .record_collected(&event.iid, &event.collected, total);
+            for neighbour in &level.neighbours {
+                save.data.unlock(&neighbour.level_iid);
+            }
+        }
+        changed = true;
+    }
+    if changed {
+        save.store();
+    }
+}
+
+fn store_settings(
+    overlay: Res<TrajectoryOverlay>,
+    control: Res<CameraControl>,
+    mut save: ResMut<SaveGame>,
+) {
+    let settings = Settings {
+        show_trajectory: overlay.enabled,
+        trajectory_tiles: overlay.tiles_ahead,
+        camera_smoothing: control.smoothing,
+    };
+    if settings != save.data.settings {
+        save.data.settings = settings;
+        save.store();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an empty directory of its own for every test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sole-save-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn solved_save(iid: &str) -> SaveData {
+        let mut save = SaveData::default();
+        save.record_solved(iid, 3, 4.5, 2);
+        save
+    }
+
+    // version 1 called best_clicks `best`
+    fn rename_best(value: &mut Value) {
+        for progress in value["levels"].as_object_mut().unwrap().values_mut() {
+            let progress = progress.as_object_mut().unwrap();
+            if let Some(best) = progress.remove("best") {
+                progress.insert("best_clicks".to_string(), best);
+            }
+        }
+    }
+
+    #[test]
+    fn older_saves_run_through_the_migrations() {
+        let old = r#"{"version": 1, "levels": {"a": {"solved": true, "best": 4}}}"#;
+        let save = upgrade_save(old, 2, &[rename_best]).unwrap();
+        assert_eq!(save.version, 2);
+        assert_eq!(save.level("a").best_clicks, Some(4));
+        assert!(save.level("a").solved);
+
+        // saves from before versioning count as version 1
+        for old in [
+            r#"{"levels": {"a": {"best": 4}}}"#,
+            r#"{"version": 0, "levels": {"a": {"best": 4}}}"#,
+        ] {
+            let save = upgrade_save(old, 2, &[rename_best]).unwrap();
+            assert_eq!(save.level("a").best_clicks, Some(4));
+        }
+
+        // saves of the current version skip them
+        let current = r#"{"version": 2, "levels": {"a": {"best": 4}}}"#;
+        let save = upgrade_save(current, 2, &[rename_best]).unwrap();
+        assert_eq!(save.level("a").best_clicks, None);
+    }
+
+    #[test]
+    fn saves_from_newer_builds_are_refused() {
+        let json = format!(r#"{{"version": {}}}"#, SAVE_VERSION + 1);
+        let error = parse_save(&json).unwrap_err();
+        assert!(matches!(error, SaveError::UnknownVersion(version) if version == SAVE_VERSION + 1));
+
+        // and left alone on disk
+        let dir = test_dir("newer");
+        let path = dir.join(SAVE_FILE);
+        fs::write(&path, &json).unwrap();
+        assert!(matches!(
+            load_save(&path),
+            Err(SaveError::UnknownVersion(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), json);
+        assert!(!with_extension(&path, "corrupt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_corrupt_save_falls_back_to_the_backup() {
+        let dir = test_dir("corrupt");
+        let path = dir.join(SAVE_FILE);
+        let backup = solved_save("a");
+        fs::write(&path, "{\"version\": 1, \"levels\": ").unwrap();
+        fs::write(
+            with_extension(&path, "bak"),
+            serde_json::to_string(&backup).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(load_save(&path).unwrap(), backup);
+        // the broken file is moved aside for inspection, not overwritten
+        assert!(!path.exists());
+        let corrupt = fs::read_to_string(with_extension(&path, "corrupt")).unwrap();
+        assert_eq!(corrupt, "{\"version\": 1, \"levels\": ");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_saves_start_fresh() {
+        let dir = test_dir("missing");
+        assert_eq!(
+            load_save(&dir.join(SAVE_FILE)).unwrap(),
+            SaveData::default()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_are_written_through_a_temporary_file() {
+        let dir = test_dir("write");
+        let path = dir.join("nested").join(SAVE_FILE);
+        let tmp = with_extension(&path, "tmp");
+        let first = solved_save("a");
+        write_save(&path, &first).unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(load_save(&path).unwrap(), first);
+
+        // a temporary file left by a crash is ignored and replaced
+        fs::write(&tmp, "half a sa").unwrap();
+        assert_eq!(load_save(&path).unwrap(), first);
+        let second = solved_save("b");
+        write_save(&path, &second).unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(load_save(&path).unwrap(), second);
+        // the previous save is kept as the backup
+        let backup = fs::read_to_string(with_extension(&path, "bak")).unwrap();
+        assert_eq!(parse_save(&backup).unwrap(), first);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::{
+    collectible::{collectibles, wants_all},
+    level::Level,
+    loading::GameAssets,
+    solver::solve,
+    world::WorldLevels,
+    AppState, GameSystemSets, LevelSolved, END_TILE,
+};
+
+const MAX_STARS: u8 = 3;
+// most clicks the solver looks for when working out a par nobody authored
+const MAX_PAR_CLICKS: usize = 3;
+
+// Components
+
+#[derive(Component)]
+struct LevelCompleteScreen;
+
+// Resources
+
+// Par values of a level, authored as the LDtk level fields `par_rays` and
+// `par_time`. Either one can be missing, its star is not awarded then.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Par {
+    pub rays: Option<i32>,
+    pub time: Option<f32>,
+}
+
+impl Par {
+    pub fn from_level(level: &Level) -> Self {
+        Par {
+            rays: level
+                .field("par_rays")
+                .and_then(|value| value.as_i64())
+                .map(|rays| rays as i32),
+            time: level
+                .field("par_time")
+                .and_then(|value| value.as_f64())
+                .map(|time| time as f32),
+        }
+    }
+
+    // authored values win, the rest is filled in from `other`
+    pub fn or(self, other: Par) -> Par {
+        Par {
+            rays: self.rays.or(other.rays),
+            time: self.time.or(other.time),
+        }
+    }
+
+    // one star for reaching the goal, one each for matching the ray and time par
+    pub fn stars(&self, clicks: i32, time: f32) -> u8 {
+        let mut stars = 1;
+        if self.rays.is_some_and(|rays| clicks <= rays) {
+            stars += 1;
+        }
+        if self.time.is_some_and(|par| time <= par) {
+            stars += 1;
+        }
+        stars
+    }
+}
+
+// par a solver worked out for levels that have none authored, keyed by level iid
+#[derive(Resource, Default)]
+pub struct ComputedPars(pub HashMap<String, Par>);
+
+// solver runs still working out a par, keyed by level iid
+#[derive(Resource, Default)]
+struct ParTasks(HashMap<String, Task<Par>>);
+
+#[derive(SystemParam)]
+pub struct Pars<'w> {
+    world_levels: WorldLevels<'w>,
+    computed: Res<'w, ComputedPars>,
+}
+
+impl<'w> Pars<'w> {
+    pub fn get(&self, iid: &str) -> Par {
+        let authored = self
+            .world_levels
+            .by_iid(iid)
+            .map(Par::from_level)
+            .unwrap_or_default();
+        authored.or(self.computed.0.get(iid).copied().unwrap_or_default())
+    }
+}
+
+pub fn star_text(stars: u8) -> String {
+    (0..MAX_STARS)
+        .map(|star| if star < stars { '★' } else { '☆' })
+        .collect()
+}
+
+// Plugin
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ComputedPars>();
+        app.init_resource::<ParTasks>();
+        app.add_system(compute_pars.in_schedule(OnEnter(AppState::LevelSelect)));
+        app.add_system(collect_pars);
+        app.add_systems((show_level_complete, close_level_complete).in_set(GameSystemSets::Logic));
+        app.add_system(despawn_level_complete.in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+// Solves every level with an End tile that lacks an authored par, once, off
+// the main thread since the search can take a while. Levels the solver gives
+// up on get an empty par, so their stars are not awarded.
+fn compute_pars(
+    world_levels: WorldLevels,
+    computed: Res<ComputedPars>,
+    mut tasks: ResMut<ParTasks>,
+) {
+    let levels: Arc<Vec<Level>> = Arc::new(world_levels.iter().cloned().collect());
+    let pool = AsyncComputeTaskPool::get();
+    for (index, level) in levels.iter().enumerate() {
+        let authored = Par::from_level(level);
+        if authored.rays.is_some() && authored.time.is_some()
+            || level.find_tile(END_TILE).is_none()
+            || computed.0.contains_key(&level.iid)
+            || tasks.0.contains_key(&level.iid)
+        {
+            continue;
+        }
+        let levels = levels.clone();
+        let task = pool.spawn(async move {
+            solve(&levels[index], &levels, MAX_PAR_CLICKS)
+                .map(|solution| solution.par())
+                .unwrap_or_default()
+        });
+        tasks.0.insert(level.iid.clone(), task);
+    }
+}
+
+fn collect_pars(mut tasks: ResMut<ParTasks>, mut computed: ResMut<ComputedPars>) {
+    tasks.0.retain(|iid, task| {
+        let Some(par) = future::block_on(future::poll_once(task)) else {
+            return true;
+        };
+        computed.0.insert(iid.clone(), par);
+        false
+    });
+}
+
+fn show_level_complete(
+    mut commands: Commands,
+    mut solved: EventReader<LevelSolved>,
+    world_levels: WorldLevels,
+    pars: Pars,
+    game_assets: Res<GameAssets>,
+    q_screen: Query<Entity, With<LevelCompleteScreen>>,
+) {
+    let Some(event) = solved.iter().last() else {
+        return;
+    };
+    for entity in &q_screen {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let par = pars.get(&event.iid);
+    let level = world_levels.by_iid(&event.iid);
+    let name = level
+        .map(|level| level.identifier.replace('_', " "))
+        .unwrap_or_default();
+    let par_text = |par: Option<String>| par.unwrap_or_else(|| "-".to_string());
+    let mut lines = vec![
+        (format!("{} complete", name), 28.),
+        (star_text(par.stars(event.clicks, event.time)), 40.),
+        (
+            format!(
+                "rays {}  par {}",
+                event.clicks,
+                par_text(par.rays.map(|rays| rays.to_string()))
+            ),
+            18.,
+        ),
+        (
+            format!(
+                "time {:.1}s  par {}",
+                event.time,
+                par_text(par.time.map(|time| format!("{:.1}s", time)))
+            ),
+            18.,
+        ),
+    ];
+    let total = level.map_or(0, |level| collectibles(level).len());
+    if total > 0 {
+        let found = event.collected.len();
+        lines.push((format!("collected {}/{}", found, total), 18.));
+        if level.is_some_and(wants_all) {
+            let outcome = if found >= total { "done" } else { "missed" };
+            lines.push((format!("collect all: {}", outcome), 18.));
+        }
+    }
+    lines.push(("enter: continue  esc: levels".to_string(), 14.));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            LevelCompleteScreen,
+        ))
+        .with_children(|root| {
+            root.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(24.)),
+                    gap: Size::height(Val::Px(8.)),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0.05, 0.05, 0.1, 0.85).into(),
+                ..Default::default()
+            })
+            .with_children(|panel| {
+                for (text, size) in lines {
+                    panel.spawn(TextBundle::from_section(
+                        text,
+                        TextStyle {
+                            font: game_assets.font.clone(),
+                            font_size: size,
+                            color: Color::WHITE,
+                        },
+                    ));
+                }
+            });
+        });
+}
+
+// the ship can fly on into the neighbouring levels once the screen is closed
+fn close_level_complete(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    q_screen: Query<Entity, With<LevelCompleteScreen>>,
+) {
+    let close = keys.any_just_pressed([KeyCode::Return, KeyCode::Space])
+        || gamepads.iter().any(|gamepad| {
+            buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+        });
+    if !close {
+        return;
+    }
+    for entity in &q_screen {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn despawn_level_complete(
+    mut commands: Commands,
+    q_screen: Query<Entity, With<LevelCompleteScreen>>,
+) {
+    for entity in &q_screen {
+        commands.entity(entity).despawn_recursive();
+    }
+}
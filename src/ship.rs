@@ -0,0 +1,150 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+
+use crate::{loading::GameAssets, Dir, GameState, GameSystemSets, Player, Ray};
+
+// radians per second the ship turns towards where it flies
+const TURN_SPEED: f32 = 3. * PI;
+// share of the sail unfolded or folded per second
+const SAIL_SPEED: f32 = 4.;
+// how wide a folded sail still is
+const FOLDED_SAIL: f32 = 0.2;
+// a drifting ship rocks by this many radians, this many times per second
+const DRIFT_ANGLE: f32 = 0.08;
+const DRIFT_RATE: f32 = 0.5;
+
+// Components
+
+// how the ship is drawn, eased every frame towards what the simulation says
+#[derive(Component, Default)]
+pub struct ShipPose {
+    // radians counter clockwise, the sprites are drawn facing up
+    facing: f32,
+    // 0 folded to 1 deployed
+    sail: f32,
+    // seconds since the ship was last caught by a beam
+    drifting: f32,
+}
+
+// the sail drawn over the hull, as far deployed as its ship's ShipPose
+#[derive(Component)]
+pub struct Sail;
+
+// Plugin
+
+pub struct ShipPlugin;
+
+impl Plugin for ShipPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(animate_ship.in_set(GameSystemSets::Logic));
+    }
+}
+
+// the folded sail, to be added as a child of the ship
+pub fn spawn_sail(game_assets: &GameAssets, commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                texture: game_assets.ship.clone(),
+                sprite: Sprite {
+                    color: Color::rgba(1., 1., 1., 0.),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0., 0., 0.1).with_scale(Vec3::new(
+                    FOLDED_SAIL,
+                    1.,
+                    1.,
+                )),
+                ..Default::default()
+            },
+            Sail,
+        ))
+        .id()
+}
+
+impl Dir {
+    // rotation of the ship sprites flying this way
+    fn facing(&self) -> f32 {
+        self.angle() - PI / 2.
+    }
+}
+
+// turns `from` towards `to` by at most `max_step` radians, the short way round
+fn turn_towards(from: f32, to: f32, max_step: f32) -> f32 {
+    let diff = (to - from + PI).rem_euclid(TAU) - PI;
+    (from + diff.clamp(-max_step, max_step)).rem_euclid(TAU)
+}
+
+// turns the ship to where it flies, deploys the sail while a lit beam
+// carries it and rocks it gently while it drifts
+#[allow(clippy::type_complexity)]
+fn animate_ship(
+    time: Res<Time>,
+    game_state: Res<GameState>,
+    q_ray: Query<&Ray>,
+    mut q_ship: Query<(&Player, &mut ShipPose, &mut Transform, &Children)>,
+    mut q_sail: Query<(&mut Sprite, &mut Transform), (With<Sail>, Without<Player>)>,
+) {
+    let delta = time.delta_seconds();
+    for (player, mut pose, mut transform, children) in &mut q_ship {
+        let (x, y) = player.tile();
+        let caught = q_ray
+            .iter()
+            .any(|ray| ray.lit(game_state.tick) && ray.covers(x, y));
+
+        if let Some(direction) = &player.direction {
+            pose.facing = turn_towards(pose.facing, direction.facing(), TURN_SPEED * delta);
+        }
+        let step = SAIL_SPEED * delta;
+        let deployed = if caught { 1. } else { 0. };
+        pose.sail += (deployed - pose.sail).clamp(-step, step);
+        pose.drifting = if caught { 0. } else { pose.drifting + delta };
+
+        let rocking = (pose.drifting * DRIFT_RATE * TAU).sin() * DRIFT_ANGLE * (1. - pose.sail);
+        transform.rotation = Quat::from_rotation_z(pose.facing + rocking);
+        for child in children {
+            if let Ok((mut sprite, mut sail_transform)) = q_sail.get_mut(*child) {
+                sprite.color.set_a(pose.sail);
+                sail_transform.scale.x = FOLDED_SAIL + (1. - FOLDED_SAIL) * pose.sail;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).rem_euclid(TAU).min((b - a).rem_euclid(TAU)) < 1e-5
+    }
+
+    #[test]
+    fn ship_sprites_face_their_direction() {
+        assert!(close(Dir::Upwards.facing(), 0.));
+        assert!(close(Dir::Leftwards.facing(), PI / 2.));
+        assert!(close(Dir::Downwards.facing(), PI));
+        assert!(close(Dir::Rightwards.facing(), -PI / 2.));
+    }
+
+    #[test]
+    fn turning_takes_the_short_way_round() {
+        // from facing right to facing up is a quarter turn counter clockwise
+        let right = Dir::Rightwards.facing();
+        let turned = turn_towards(right, Dir::Upwards.facing(), 0.1);
+        assert!(close(turned, right + 0.1));
+
+        let turned = turn_towards(0.1, TAU - 0.1, 1.);
+        assert!(close(turned, -0.1));
+    }
+
+    #[test]
+    fn turning_stops_on_the_target() {
+        let mut facing = Dir::Upwards.facing();
+        for _ in 0..100 {
+            facing = turn_towards(facing, Dir::Downwards.facing(), 0.1);
+        }
+        assert!(close(facing, PI));
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use crate::{
+    level::Level,
+    replay::{Activation, Outcome, Simulation},
+    score::Par,
+    SIM_STEP,
+};
+
+// ticks a click gets to bring the fleet to rest or to its goals
+const MAX_SETTLE_TICKS: u32 = 60 * 30;
+
+// Solver
+//
+// Searches a level breadth first for the fewest casters to click, clicking
+// the next one only once every ship came to rest. Sessions resting with the
+// same Simulation::rest_key play on the same, so each is explored once and
+// only remembers how many click sequences lead to it. Casters, asteroids and
+// pulses moving while the fleet rests are not waited for, so levels that
+// need a click timed to them are not solved.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    // the fastest of the shortest solutions, replays with verify
+    pub activations: Vec<Activation>,
+    // ticks until the fleet reached its goals
+    pub ticks: u32,
+    // how many different click sequences of the same length solve the level
+    pub count: u64,
+}
+
+impl Solution {
+    pub fn clicks(&self) -> usize {
+        self.activations.len()
+    }
+
+    // the solution's clicks and its time rounded up to a tenth of a second
+    pub fn par(&self) -> Par {
+        Par {
+            rays: Some(self.clicks() as i32),
+            time: Some((self.ticks as f32 * SIM_STEP * 10.).ceil() / 10.),
+        }
+    }
+}
+
+// a session at rest the search goes on from
+struct Node<'a> {
+    simulation: Simulation<'a>,
+    activations: Vec<Activation>,
+    count: u64,
+}
+
+// the casters of a level, level local and row by row
+pub fn level_casters(level: &Level) -> Vec<(i32, i32)> {
+    let mut casters: Vec<(i32, i32)> = (2..=5).flat_map(|value| level.find_tiles(value)).collect();
+    casters.sort_by_key(|(x, y)| (*y, *x));
+    casters
+}
+
+// runs until the fleet rests or the session ends, None if it never rests
+fn settle(simulation: &mut Simulation) -> Option<Outcome> {
+    for _ in 0..MAX_SETTLE_TICKS {
+        let outcome = simulation.step();
+        if outcome != Outcome::Running || simulation.at_rest() {
+            return Some(outcome);
+        }
+    }
+    None
+}
+
+// the shortest solutions of `level` with at most `max_clicks` clicks, None
+// if there are none
+pub fn solve(level: &Level, levels: &[Level], max_clicks: usize) -> Option<Solution> {
+    let mut start = Simulation::new(level, levels);
+    match settle(&mut start)? {
+        Outcome::Running => (),
+        Outcome::Solved => {
+            return Some(Solution {
+                activations: Vec::new(),
+                ticks: start.tick(),
+                count: 1,
+            })
+        }
+        Outcome::Lost => return None,
+    }
+
+    let casters = level_casters(level);
+    let mut layer = vec![Node {
+        simulation: start,
+        activations: Vec::new(),
+        count: 1,
+    }];
+    for _ in 0..max_clicks {
+        let mut solution: Option<Solution> = None;
+        let mut next: Vec<Node> = Vec::new();
+        let mut seen: HashMap<Vec<i32>, usize> = HashMap::new();
+        for node in &layer {
+            for &(x, y) in &casters {
+                let mut simulation = node.simulation.clone();
+                let tick = simulation.tick();
+                simulation.activate(x, y);
+                let mut activations = node.activations.clone();
+                activations.push(Activation { tick, x, y });
+                match settle(&mut simulation) {
+                    Some(Outcome::Running) => {
+                        let key = simulation.rest_key();
+                        match seen.get(&key) {
+                            Some(&index) => {
+                                next[index].count = next[index].count.saturating_add(node.count)
+                            }
+                            None => {
+                                seen.insert(key, next.len());
+                                next.push(Node {
+                                    simulation,
+                                    activations,
+                                    count: node.count,
+                                });
+                            }
+                        }
+                    }
+                    Some(Outcome::Solved) => match &mut solution {
+                        Some(solution) => {
+                            solution.count = solution.count.saturating_add(node.count);
+                            if simulation.tick() < solution.ticks {
+                                solution.activations = activations;
+                                solution.ticks = simulation.tick();
+                            }
+                        }
+                        None => {
+                            solution = Some(Solution {
+                                activations,
+                                ticks: simulation.tick(),
+                                count: node.count,
+                            })
+                        }
+                    },
+                    Some(Outcome::Lost) | None => (),
+                }
+            }
+        }
+        if solution.is_some() {
+            return solution;
+        }
+        layer = next;
+    }
+    None
+}
@@ -0,0 +1,1656 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    ecs::event::ManualEventReader,
+    input::{
+        mouse::MouseButtonInput,
+        touch::{TouchInput, TouchPhase},
+        ButtonState, InputPlugin,
+    },
+    prelude::*,
+    render::camera::camera_system,
+    time::TimeUpdateStrategy,
+    window::{PrimaryWindow, WindowCreated, WindowResized},
+};
+use serde_json::json;
+
+use crate::{
+    caster::CasterPlugin,
+    collectible::{Collectible, CollectiblePlugin},
+    fleet::{FleetMember, FleetPlugin},
+    generator::{add_generated_level, generate, GeneratorConfig},
+    hazards::HazardPlugin,
+    level::{read_project_levels, LdtkProject, Level},
+    lint::{lint_level, Lint},
+    loading::{GameAssets, PROJECT_PATH},
+    picking::{PickCamera, PickState, Pickable, PickingPlugin},
+    preview::{BeamPreview, PreviewPlugin},
+    replay::{read_replay, verify, Activation, Replay, ReplayError},
+    save::SaveData,
+    score::{ComputedPars, Par, ScorePlugin},
+    ship::ShipPlugin,
+    solver::solve,
+    triggers::TriggerPlugin,
+    world::{CurrentLevel, LevelEntity, SelectedLevel, WorldStreamingPlugin},
+    AppState, Dir, GamePlugin, GameState, LevelGrid, LevelSolved, Player, Ray, RayCaster,
+    PLAYER_SPEED, SIM_STEP, TILE_SIZE,
+};
+
+const TEST_LEVEL_IID: &str = "00000000-0000-0000-0000-000000000001";
+
+// ship starts at 2, 2, the goal is at 4, 4 and an obstacle at 6, 2
+const OPEN_LEVEL: &[&str] = &[
+    "#vvvvvvv#",
+    ">.......<",
+    ">.S...#.<",
+    ">.......<",
+    ">...E...<",
+    "#^^^^^^^#",
+];
+
+// authored Lightray: source tile, destination tile and priority
+type LevelRay = ((i32, i32), (i32, i32), i32);
+
+// An .ldtkl level the way LDtk writes it, built from one string per IntGrid row:
+// '.' empty, '#' obstacle, 'v' '^' '>' '<' casters shooting down, up, right and
+// left, 'S' start, 'E' end and 'O' black hole.
+fn ldtk_level(rows: &[&str], rays: &[LevelRay]) -> String {
+    level_json(rows, rays).to_string()
+}
+
+fn level_json(rows: &[&str], rays: &[LevelRay]) -> serde_json::Value {
+    let (width, height) = (rows[0].len(), rows.len());
+    let int_grid: Vec<i32> = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .map(|tile| match tile {
+            '#' => 1,
+            'v' => 2,
+            '^' => 3,
+            '>' => 4,
+            '<' => 5,
+            'S' => 6,
+            'E' => 7,
+            'O' => 9,
+            _ => 0,
+        })
+        .collect();
+    let entities: Vec<_> = rays
+        .iter()
+        .map(|((x, y), (dest_x, dest_y), priority)| {
+            json!({
+                "__identifier": "Lightray",
+                "__grid": [x, y],
+                "fieldInstances": [
+                    { "__identifier": "destination", "__value": { "cx": dest_x, "cy": dest_y } },
+                    { "__identifier": "priority", "__value": priority },
+                ],
+            })
+        })
+        .collect();
+    let layer = |layer_type: &str, int_grid_csv: Vec<i32>, entity_instances: Vec<_>| {
+        json!({
+            "__type": layer_type,
+            "__cWid": width,
+            "__cHei": height,
+            "__gridSize": 16,
+            "intGridCsv": int_grid_csv,
+            "entityInstances": entity_instances,
+        })
+    };
+    json!({
+        "identifier": "Test_level",
+        "iid": TEST_LEVEL_IID,
+        "worldX": 0,
+        "worldY": 0,
+        "__bgColor": "#000000",
+        "fieldInstances": [],
+        "layerInstances": [
+            layer("Entities", Vec::new(), entities),
+            layer("IntGrid", int_grid, Vec::new()),
+        ],
+        "__neighbours": [],
+    })
+}
+
+// OPEN_LEVEL with a `ray_order` and Caster_layer entities at the given casters
+fn ordered_level(order: &str, caster_layers: &[((i32, i32), i32)]) -> String {
+    let mut level = level_json(OPEN_LEVEL, &[]);
+    level["fieldInstances"] = json!([{ "__identifier": "ray_order", "__value": order }]);
+    let entities = level["layerInstances"][0]["entityInstances"]
+        .as_array_mut()
+        .unwrap();
+    for ((x, y), layer) in caster_layers {
+        entities.push(json!({
+            "__identifier": "Caster_layer",
+            "__grid": [x, y],
+            "fieldInstances": [{ "__identifier": "layer", "__value": layer }],
+        }));
+    }
+    level.to_string()
+}
+
+// `rows` with extra entities, each given by identifier, tile and field values
+fn entity_level(rows: &[&str], extra: &[(&str, (i32, i32), serde_json::Value)]) -> String {
+    let mut level = level_json(rows, &[]);
+    let entities = level["layerInstances"][0]["entityInstances"]
+        .as_array_mut()
+        .unwrap();
+    for (identifier, (x, y), fields) in extra {
+        let fields: Vec<_> = fields
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(identifier, value)| json!({ "__identifier": identifier, "__value": value }))
+            .collect();
+        entities.push(json!({
+            "__identifier": identifier,
+            "__grid": [x, y],
+            "iid": entity_iid(identifier, (*x, *y)),
+            "fieldInstances": fields,
+        }));
+    }
+    level.to_string()
+}
+
+// iid of an entity of `entity_level`, for EntityRef fields
+fn entity_iid(identifier: &str, (x, y): (i32, i32)) -> String {
+    format!("{}_{}_{}", identifier, x, y)
+}
+
+// sets level fields of a level built above, values by identifier
+fn with_level_fields(level: &str, fields: serde_json::Value) -> String {
+    let mut level: serde_json::Value = serde_json::from_str(level).unwrap();
+    let fields: Vec<_> = fields
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(identifier, value)| json!({ "__identifier": identifier, "__value": value }))
+        .collect();
+    level["fieldInstances"] = json!(fields);
+    level.to_string()
+}
+
+// The game without window, renderer or asset files, already in the level.
+// Every update advances the clock by exactly one simulation tick.
+struct TestGame {
+    app: App,
+    now: Instant,
+}
+
+impl TestGame {
+    fn new(level: &str) -> Self {
+        TestGame::with_levels(&[level])
+    }
+
+    // a game of several levels, starting in the first
+    fn with_levels(levels: &[&str]) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(TransformPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(GamePlugin)
+            .add_plugin(CasterPlugin)
+            .add_plugin(HazardPlugin)
+            .add_plugin(TriggerPlugin)
+            .add_plugin(CollectiblePlugin)
+            .add_plugin(FleetPlugin)
+            .add_plugin(ShipPlugin)
+            .add_plugin(ScorePlugin)
+            .add_plugin(PickingPlugin)
+            .add_plugin(PreviewPlugin)
+            .add_plugin(WorldStreamingPlugin);
+        // the camera's viewport, which picking needs, without a renderer
+        app.add_asset::<Image>()
+            .add_event::<WindowCreated>()
+            .add_event::<WindowResized>()
+            .add_system(camera_system::<OrthographicProjection>.in_base_set(CoreSet::PostUpdate));
+
+        let levels: Vec<Level> = levels
+            .iter()
+            .map(|level| serde_json::from_str(level).unwrap())
+            .collect();
+        let selected = SelectedLevel(Some(levels[0].iid.clone()));
+        let levels = levels
+            .into_iter()
+            .map(|level| app.world.resource_mut::<Assets<Level>>().add(level))
+            .collect();
+        let project = app
+            .world
+            .resource_mut::<Assets<LdtkProject>>()
+            .add(LdtkProject {
+                levels,
+                int_grid_colors: HashMap::new(),
+            });
+        app.insert_resource(GameAssets {
+            project,
+            ..Default::default()
+        });
+        app.insert_resource(selected);
+        // pick_input wants a camera rendering to the primary window
+        app.world.spawn((Window::default(), PrimaryWindow));
+        app.world.spawn((
+            Camera::default(),
+            OrthographicProjection::default(),
+            TransformBundle::from_transform(Transform::from_xyz(0., 0., 999.9)),
+            PickCamera::default(),
+        ));
+
+        let now = Instant::now();
+        app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::InGame);
+        // enters the level, the first update only starts the clock and runs no tick
+        app.update();
+        TestGame { app, now }
+    }
+
+    fn update(&mut self) {
+        let period: Duration = self.app.world.resource::<FixedTime>().period;
+        self.now += period;
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualInstant(self.now));
+        self.app.update();
+    }
+
+    fn ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    // clicks the caster at a world tile, taking one update
+    fn click(&mut self, x: i32, y: i32) {
+        let caster = self
+            .app
+            .world
+            .query::<(Entity, &RayCaster)>()
+            .iter(&self.app.world)
+            .find(|(_, caster)| caster.pos_x == x && caster.pos_y == y)
+            .map(|(entity, _)| entity)
+            .expect("no caster at this tile");
+        self.app.world.resource_mut::<PickState>().selected = Some(caster);
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            self.app.world.send_event(MouseButtonInput {
+                button: MouseButton::Left,
+                state,
+            });
+            self.update();
+        }
+    }
+
+    fn window(&mut self) -> Mut<'_, Window> {
+        self.app
+            .world
+            .query_filtered::<&mut Window, With<PrimaryWindow>>()
+            .single_mut(&mut self.app.world)
+    }
+
+    // where the centre of a world tile is in the window, from the bottom left
+    // like the cursor
+    fn cursor_position(&mut self, x: i32, y: i32) -> Vec2 {
+        let (camera, transform) = self
+            .app
+            .world
+            .query::<(&Camera, &GlobalTransform)>()
+            .single(&self.app.world);
+        let world_pos = Vec3::new(x as f32 * TILE_SIZE, -y as f32 * TILE_SIZE, 0.);
+        camera.world_to_viewport(transform, world_pos).unwrap()
+    }
+
+    // moves the cursor onto a world tile and clicks there, taking two updates
+    // like `click`
+    fn click_at(&mut self, x: i32, y: i32) {
+        let position = self.cursor_position(x, y);
+        self.window().set_cursor_position(Some(position));
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            self.app.world.send_event(MouseButtonInput {
+                button: MouseButton::Left,
+                state,
+            });
+            self.update();
+        }
+    }
+
+    // a finger touching down on or lifting off a world tile, taking one update
+    fn touch(&mut self, x: i32, y: i32, phase: TouchPhase) {
+        let position = self.cursor_position(x, y);
+        // touches start at the top left
+        let height = self.window().height();
+        self.app.world.send_event(TouchInput {
+            phase,
+            position: Vec2::new(position.x, height - position.y),
+            force: None,
+            id: 0,
+        });
+        self.update();
+    }
+
+    // entities of the beam preview currently drawn
+    fn previews(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<(), With<BeamPreview>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    fn game_state(&self) -> &GameState {
+        self.app.world.resource::<GameState>()
+    }
+
+    fn player(&mut self) -> Player {
+        self.app
+            .world
+            .query::<&Player>()
+            .single(&self.app.world)
+            .clone()
+    }
+
+    // ray starting at a world tile, with its entity
+    fn ray_at(&mut self, x: i32, y: i32) -> (Entity, Ray) {
+        self.app
+            .world
+            .query::<(Entity, &Ray)>()
+            .iter(&self.app.world)
+            .find(|(_, ray)| (ray.src_x, ray.src_y) == (x, y))
+            .map(|(entity, ray)| (entity, ray.clone()))
+            .expect("no ray at this tile")
+    }
+
+    // z of the sprites of the ray starting at a world tile
+    fn ray_z(&mut self, x: i32, y: i32) -> f32 {
+        let (ray, _) = self.ray_at(x, y);
+        let photon = self.app.world.get::<Children>(ray).unwrap()[0];
+        self.app
+            .world
+            .get::<Transform>(photon)
+            .unwrap()
+            .translation
+            .z
+    }
+
+    // alpha of the photons of the ray starting at a world tile
+    fn ray_alpha(&mut self, x: i32, y: i32) -> f32 {
+        let (ray, _) = self.ray_at(x, y);
+        let photon = self.app.world.get::<Children>(ray).unwrap()[0];
+        self.app
+            .world
+            .get::<TextureAtlasSprite>(photon)
+            .unwrap()
+            .color
+            .a()
+    }
+
+    // every ship in fleet order
+    fn ships(&mut self) -> Vec<Player> {
+        let mut ships: Vec<(Player, usize)> = self
+            .app
+            .world
+            .query::<(&Player, &FleetMember)>()
+            .iter(&self.app.world)
+            .map(|(player, member)| (player.clone(), member.index))
+            .collect();
+        ships.sort_by_key(|(_, index)| *index);
+        ships.into_iter().map(|(player, _)| player).collect()
+    }
+
+    fn caster(&mut self, x: i32, y: i32) -> Option<RayCaster> {
+        self.app
+            .world
+            .query::<&RayCaster>()
+            .iter(&self.app.world)
+            .find(|caster| (caster.pos_x, caster.pos_y) == (x, y))
+            .cloned()
+    }
+
+    // Clicks the casters at the world tiles in turn, `wait` ticks apart, and
+    // plays on until the current level is solved. Verifying the clicks as a
+    // replay against `levels` has to reach the goal on the same tick.
+    fn assert_replay_matches(&mut self, clicks: &[(i32, i32)], wait: u32, levels: &[&str]) {
+        let iid = self.app.world.resource::<CurrentLevel>().iid.clone();
+        let mut replay = Replay::new(&iid);
+        for &(x, y) in clicks {
+            self.click(x, y);
+            replay.activations.push(Activation {
+                tick: self.game_state().tick - 1,
+                x,
+                y,
+            });
+            self.ticks(wait);
+        }
+        while !self.game_state().solved.contains(&iid) {
+            assert!(self.game_state().tick < 600, "the level was never solved");
+            self.update();
+        }
+
+        let levels: Vec<Level> = levels
+            .iter()
+            .map(|level| serde_json::from_str(level).unwrap())
+            .collect();
+        assert_eq!(verify(&replay, &levels).unwrap(), self.game_state().tick);
+    }
+
+    fn rays(&mut self) -> Vec<(Ray, LevelEntity)> {
+        self.app
+            .world
+            .query::<(&Ray, &LevelEntity)>()
+            .iter(&self.app.world)
+            .map(|(ray, level_entity)| (ray.clone(), level_entity.clone()))
+            .collect()
+    }
+}
+
+#[test]
+fn ship_waits_at_the_start_without_a_ray() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.ticks(60);
+
+    let player = game.player();
+    assert_eq!((player.x, player.y), (2., 2.));
+    assert!(player.direction.is_none());
+    assert_eq!(game.game_state().tick, 60);
+}
+
+#[test]
+fn clicked_caster_spawns_a_ray_up_to_the_next_obstacle() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(0, 2);
+
+    let rays = game.rays();
+    assert_eq!(rays.len(), 1);
+    let (ray, level_entity) = &rays[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 2, 5, 2));
+    assert!(ray.horizontal && ray.reversed);
+    assert_eq!(level_entity.0, TEST_LEVEL_IID);
+    assert_eq!(game.game_state().activations, 1);
+}
+
+#[test]
+fn tapping_a_caster_fires_it() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.touch(0, 2, TouchPhase::Started);
+    game.ticks(5);
+    assert!(game.rays().is_empty());
+    game.touch(0, 2, TouchPhase::Ended);
+
+    let rays = game.rays();
+    assert_eq!(rays.len(), 1);
+    assert_eq!((rays[0].0.src_x, rays[0].0.src_y), (0, 2));
+    assert_eq!(game.game_state().activations, 1);
+    // a tap selects without previewing
+    game.update();
+    assert_eq!(game.previews(), 0);
+}
+
+#[test]
+fn long_pressing_a_caster_previews_its_beam_without_firing() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.touch(0, 2, TouchPhase::Started);
+    game.ticks(5);
+    assert_eq!(game.previews(), 0);
+
+    game.ticks(30);
+    assert!(game.app.world.resource::<PickState>().long_press);
+    assert!(game.previews() > 0);
+
+    // letting go ends the preview and fires nothing
+    game.touch(0, 2, TouchPhase::Ended);
+    assert_eq!(game.previews(), 0);
+    assert!(game.rays().is_empty());
+    assert_eq!(game.game_state().activations, 0);
+}
+
+#[test]
+fn clicks_on_disabled_casters_do_nothing() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    let set_enabled = |game: &mut TestGame, enabled: bool| {
+        let (_, mut pickable) = game
+            .app
+            .world
+            .query::<(&RayCaster, &mut Pickable)>()
+            .iter_mut(&mut game.app.world)
+            .find(|(caster, _)| (caster.pos_x, caster.pos_y) == (0, 2))
+            .unwrap();
+        pickable.enabled = enabled;
+    };
+    set_enabled(&mut game, false);
+    game.click_at(0, 2);
+    assert!(game.rays().is_empty());
+    assert_eq!(game.game_state().activations, 0);
+
+    set_enabled(&mut game, true);
+    game.click_at(0, 2);
+    assert_eq!(game.rays().len(), 1);
+}
+
+#[test]
+fn beam_stops_before_the_opposite_caster() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(4, 0);
+
+    let (ray, _) = &game.rays()[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (4, 0, 4, 4));
+    assert!(!ray.horizontal && !ray.reversed);
+}
+
+#[test]
+fn ship_moves_along_a_ray_one_step_per_tick() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(0, 2);
+    let start = game.player();
+    game.ticks(30);
+
+    let player = game.player();
+    let expected = start.x + 30. * SIM_STEP * PLAYER_SPEED;
+    assert!(
+        (player.x - expected).abs() < 1e-4,
+        "{} != {}",
+        player.x,
+        expected
+    );
+    assert_eq!(player.y, start.y);
+    assert!(matches!(player.direction, Some(Dir::Rightwards)));
+}
+
+#[test]
+fn ship_turns_onto_an_older_crossing_ray_and_reaches_the_goal() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(4, 0);
+    game.click(0, 2);
+    game.ticks(240);
+
+    // the beam ends on the goal, so the ship stops on it
+    let player = game.player();
+    assert_eq!(player.tile(), (4, 4));
+    assert!(player.direction.is_none());
+    assert!(game.game_state().solved.contains(TEST_LEVEL_IID));
+}
+
+#[test]
+fn newer_rays_lie_below_older_ones() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(0, 2);
+    // crosses the ship's beam at 4, 2, but is clicked later
+    game.click(4, 0);
+    game.ticks(120);
+
+    // passes the junction and stops before the obstacle
+    assert_eq!(game.player().tile(), (5, 2));
+}
+
+#[test]
+fn lowest_priority_value_wins_where_level_rays_overlap() {
+    let rows = &["#vvvvv#", ">.....<", ">.S...<", ">.....<", "#^^^^^#"];
+    let horizontal = ((1, 2), (5, 2));
+    let vertical = ((2, 1), (2, 3));
+
+    let mut game = TestGame::new(&ldtk_level(
+        rows,
+        &[(horizontal.0, horizontal.1, 1), (vertical.0, vertical.1, 2)],
+    ));
+    game.ticks(20);
+    assert!(matches!(game.player().direction, Some(Dir::Rightwards)));
+
+    let mut game = TestGame::new(&ldtk_level(
+        rows,
+        &[(horizontal.0, horizontal.1, 2), (vertical.0, vertical.1, 1)],
+    ));
+    game.ticks(20);
+    assert!(matches!(game.player().direction, Some(Dir::Downwards)));
+}
+
+#[test]
+fn newest_ray_on_top_when_the_level_asks_for_it() {
+    let mut game = TestGame::new(&ordered_level("Newest_on_top", &[]));
+    game.click(0, 2);
+    game.click(4, 0);
+    game.ticks(120);
+
+    assert_eq!(game.player().tile(), (4, 4));
+    assert!(game.ray_z(4, 0) > game.ray_z(0, 2));
+}
+
+#[test]
+fn caster_layers_order_rays_before_firing_order() {
+    let level = ordered_level("Layers", &[((0, 2), -1), ((4, 0), 1)]);
+    let mut game = TestGame::new(&level);
+    // fired first, but on a layer below the horizontal beam
+    game.click(4, 0);
+    game.click(0, 2);
+    game.ticks(120);
+
+    assert_eq!(game.player().tile(), (5, 2));
+    assert!(game.ray_z(0, 2) > game.ray_z(4, 0));
+}
+
+#[test]
+fn casters_without_a_layer_share_layer_zero() {
+    let level = ordered_level("Layers", &[((4, 0), 0)]);
+    let mut game = TestGame::new(&level);
+    game.click(4, 0);
+    game.click(0, 2);
+    game.ticks(120);
+
+    // same layer, so the older vertical beam stays on top
+    assert_eq!(game.player().tile(), (4, 4));
+}
+
+#[test]
+fn headless_verification_agrees_with_the_game() {
+    let level = ldtk_level(OPEN_LEVEL, &[]);
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(4, 0), (0, 2)], 0, &[&level]);
+}
+
+// casters down the left edge with free tiles below them, the ship starts at 2, 2
+const RAIL_LEVEL: &[&str] = &[
+    "#########",
+    ">.......#",
+    "..S.....#",
+    "........#",
+    "....E...#",
+    "#########",
+];
+
+#[test]
+fn beams_follow_casters_sliding_along_their_rail() {
+    let level = entity_level(
+        RAIL_LEVEL,
+        &[(
+            "Caster_motion",
+            (0, 1),
+            json!({ "rail": [{ "cx": 0, "cy": 3 }], "rail_step": 0.5 }),
+        )],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(0, 1);
+    game.ticks(30);
+
+    // one tile down, now through the ship
+    let rays = game.rays();
+    assert_eq!(rays.len(), 1);
+    let (ray, _) = &rays[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 2, 7, 2));
+    assert!(game.caster(0, 1).is_none());
+    assert!(matches!(game.player().direction, Some(Dir::Rightwards)));
+
+    game.ticks(30);
+    let (ray, _) = &game.rays()[0];
+    assert_eq!((ray.src_y, ray.dest_y), (3, 3));
+    // the ship lost its ray and waits on the next centre, until the caster
+    // slides back up
+    game.ticks(20);
+    let player = game.player();
+    assert!(player.direction.is_none());
+    assert_eq!(player.y, 2.);
+    game.ticks(10);
+    assert!(matches!(game.player().direction, Some(Dir::Rightwards)));
+}
+
+#[test]
+fn timed_casters_turn_their_beam_clockwise() {
+    let rows = &[
+        "#########",
+        "#.S.....#",
+        "#.......#",
+        ">.......#",
+        "........#",
+        "#########",
+    ];
+    let mut game = TestGame::new(&entity_level(
+        rows,
+        &[("Caster_motion", (0, 3), json!({ "turn_every": 1. }))],
+    ));
+    game.click(0, 3);
+    let (ray, _) = &game.rays()[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 3, 7, 3));
+
+    game.ticks(60);
+    assert!(game.caster(0, 3).unwrap().dir == Dir::Downwards);
+    let (ray, _) = &game.rays()[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 3, 0, 4));
+    assert!(!ray.horizontal);
+}
+
+#[test]
+fn zero_second_timers_keep_casters_still_and_beams_dark() {
+    let rows = &[
+        "#########",
+        "#.S.....#",
+        ">.......#",
+        "#.......#",
+        "#########",
+    ];
+    let mut game = TestGame::new(&entity_level(
+        rows,
+        &[
+            ("Caster_motion", (0, 2), json!({ "turn_every": 0. })),
+            ("Caster_pulse", (0, 2), json!({ "duration": 0. })),
+        ],
+    ));
+    game.click(0, 2);
+    game.ticks(60);
+    assert!(game.caster(0, 2).unwrap().dir == Dir::Rightwards);
+    let (ray, _) = &game.rays()[0];
+    assert!(!ray.lit(game.game_state().tick));
+}
+
+#[test]
+fn clicks_turn_casters_before_they_fire() {
+    let rows = &[
+        "#########",
+        "#.S.....#",
+        ">.......#",
+        "........#",
+        "#########",
+    ];
+    let mut game = TestGame::new(&entity_level(
+        rows,
+        &[("Caster_motion", (0, 2), json!({ "turn_on_click": true }))],
+    ));
+    game.click(0, 2);
+    let (ray, _) = &game.rays()[0];
+    assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 2, 0, 3));
+
+    // turns left off the grid, the first beam turns with it
+    game.click(0, 2);
+    game.update();
+    let rays = game.rays();
+    assert_eq!(rays.len(), 2);
+    for (ray, _) in &rays {
+        assert_eq!((ray.src_x, ray.src_y, ray.dest_x, ray.dest_y), (0, 2, 0, 2));
+    }
+}
+
+#[test]
+fn headless_verification_agrees_with_moving_casters() {
+    let rows = &[
+        "#########",
+        ">.....>.#",
+        "..S.....#",
+        "........#",
+        "......E.#",
+        "#########",
+    ];
+    let level = entity_level(
+        rows,
+        &[
+            (
+                "Caster_motion",
+                (0, 1),
+                json!({ "rail": [{ "cx": 0, "cy": 2 }], "rail_step": 3. }),
+            ),
+            ("Caster_motion", (6, 1), json!({ "turn_on_click": true })),
+        ],
+    );
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(6, 1), (0, 1)], 0, &[&level]);
+}
+
+#[test]
+fn pulsing_rays_only_carry_the_ship_while_lit() {
+    // lit for the second half of every second
+    let ray = json!({
+        "destination": { "cx": 5, "cy": 2 },
+        "priority": 0,
+        "period": 1.,
+        "lit_for": 0.5,
+        "phase": 0.5,
+    });
+    let mut game = TestGame::new(&entity_level(OPEN_LEVEL, &[("Lightray", (1, 2), ray)]));
+    game.ticks(20);
+    assert!(game.player().direction.is_none());
+    assert_eq!(game.ray_alpha(1, 2), 0.);
+
+    game.ticks(20);
+    assert!(matches!(game.player().direction, Some(Dir::Rightwards)));
+    assert_eq!(game.ray_alpha(1, 2), 1.);
+
+    // out again when the ship reaches the next centre, so it waits there
+    game.ticks(40);
+    let player = game.player();
+    assert!(player.direction.is_none());
+    assert_eq!((player.x, player.y), (3., 2.));
+    assert_eq!(game.ray_alpha(1, 2), 0.);
+
+    game.ticks(20);
+    assert!(game.player().x > 3.);
+}
+
+#[test]
+fn caster_beams_go_out_after_their_duration() {
+    let level = entity_level(
+        OPEN_LEVEL,
+        &[("Caster_pulse", (0, 2), json!({ "duration": 1. }))],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(0, 2);
+    game.ticks(90);
+
+    // two tiles in one second, then stopped on the centre it reached
+    let player = game.player();
+    assert!(player.direction.is_none());
+    assert_eq!((player.x, player.y), (4., 2.));
+    let (ray, _) = &game.rays()[0];
+    assert!(!ray.lit(game.game_state().tick));
+}
+
+#[test]
+fn headless_verification_agrees_with_pulsing_beams() {
+    let level = entity_level(
+        OPEN_LEVEL,
+        &[(
+            "Caster_pulse",
+            (0, 2),
+            json!({ "period": 1., "lit_for": 0.5 }),
+        )],
+    );
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(4, 0), (0, 2)], 0, &[&level]);
+}
+
+#[test]
+fn timed_beams_keep_their_timing_across_level_borders() {
+    // the caster's beam carries the ship east into the second level
+    let first = ["######", "#.....", ">S....", "#.....", "######"];
+    let second = [
+        "#########",
+        "#.......#",
+        "........#",
+        "#.......#",
+        "#########",
+    ];
+    let mut first = level_json(&first, &[]);
+    let second_iid = "00000000-0000-0000-0000-000000000002";
+    first["__neighbours"] = json!([{ "levelIid": second_iid, "dir": "e" }]);
+    // out for good after half a second, and lit every first half second
+    let rays = [
+        (
+            "Lightray",
+            (1, 1),
+            json!({ "destination": { "cx": 7, "cy": 1 }, "priority": 0, "duration": 0.5 }),
+        ),
+        (
+            "Lightray",
+            (1, 3),
+            json!({ "destination": { "cx": 7, "cy": 3 }, "priority": 0, "period": 1., "lit_for": 0.5 }),
+        ),
+    ];
+    let mut second: serde_json::Value =
+        serde_json::from_str(&entity_level(&second, &rays)).unwrap();
+    second["identifier"] = json!("Second_level");
+    second["iid"] = json!(second_iid);
+    second["worldX"] = json!(6 * 16);
+    second["__neighbours"] = json!([{ "levelIid": TEST_LEVEL_IID, "dir": "w" }]);
+    let mut game = TestGame::with_levels(&[&first.to_string(), &second.to_string()]);
+
+    let ray_lit = |game: &mut TestGame, x: i32, y: i32| {
+        let tick = game.game_state().tick;
+        game.ray_at(x, y).1.lit(tick)
+    };
+    let check = |game: &mut TestGame, clock: u32| {
+        assert!(!ray_lit(game, 7, 1), "tick {}", clock);
+        assert_eq!(ray_lit(game, 7, 3), clock % 60 < 30, "tick {}", clock);
+    };
+    game.ticks(75);
+    game.click(0, 2);
+    // ticks since the second level got spawned next to the first
+    let mut clock = 77;
+    check(&mut game, clock);
+    while game.app.world.resource::<CurrentLevel>().iid != second_iid {
+        assert!(clock < 600, "the ship never crossed into the second level");
+        game.update();
+        clock += 1;
+        check(&mut game, clock);
+    }
+    // level ticks started over, the beams did not
+    assert!(game.game_state().tick < 2);
+    for _ in 0..120 {
+        game.update();
+        clock += 1;
+        check(&mut game, clock);
+    }
+}
+
+#[test]
+fn ships_sailing_into_a_grid_level_land_on_a_tile_centre() {
+    // the caster's beam pushes the ship east across the border
+    let first = ["######", "#.....", ">S....", "#.....", "######"];
+    let second = [
+        "#########",
+        "#.......#",
+        "........#",
+        "#.......#",
+        "#########",
+    ];
+    let mut first = level_json(&first, &[]);
+    let second_iid = "00000000-0000-0000-0000-000000000002";
+    first["fieldInstances"] = json!([{ "__identifier": "ship_mode", "__value": "Sail" }]);
+    first["__neighbours"] = json!([{ "levelIid": second_iid, "dir": "e" }]);
+    let mut second = level_json(&second, &[]);
+    second["identifier"] = json!("Second_level");
+    second["iid"] = json!(second_iid);
+    second["worldX"] = json!(6 * 16);
+    second["__neighbours"] = json!([{ "levelIid": TEST_LEVEL_IID, "dir": "w" }]);
+    let mut game = TestGame::with_levels(&[&first.to_string(), &second.to_string()]);
+
+    game.click(0, 2);
+    let mut ticks = 0;
+    while game.app.world.resource::<CurrentLevel>().iid != second_iid {
+        assert!(ticks < 600, "the ship never crossed into the second level");
+        game.update();
+        ticks += 1;
+    }
+    let player = game.player();
+    assert_eq!((player.x.fract(), player.y.fract()), (0., 0.));
+    assert_eq!(player.velocity, Vec2::ZERO);
+
+    // the beam carries the ship on from centre to centre up to the wall
+    game.ticks(600);
+    let player = game.player();
+    assert_eq!((player.x, player.y), (13., 2.));
+    assert!(player.direction.is_none());
+}
+
+#[test]
+fn sail_ships_drift_on_after_their_beam_went_out() {
+    let level = entity_level(
+        OPEN_LEVEL,
+        &[("Caster_pulse", (0, 2), json!({ "duration": 0.5 }))],
+    );
+    let mut game = TestGame::new(&with_level_fields(&level, json!({ "ship_mode": "Sail" })));
+    game.click(0, 2);
+    game.ticks(30);
+    let pushed = game.player().velocity.x;
+    assert!(pushed > 0.);
+
+    game.ticks(20);
+    let player = game.player();
+    assert!(matches!(player.direction, Some(Dir::Rightwards)));
+    assert!(player.velocity.x > 0. && player.velocity.x < pushed);
+
+    // comes to rest against the obstacle at 6, 2
+    game.ticks(600);
+    let player = game.player();
+    assert_eq!(player.velocity, Vec2::ZERO);
+    assert!(player.x > 4. && player.x < 5.5);
+    assert_eq!(player.y, 2.);
+}
+
+#[test]
+fn headless_verification_agrees_with_sail_ships() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S.E.#.<",
+        ">.......<",
+        "#^^^^^^^#",
+    ];
+    let level = with_level_fields(&ldtk_level(rows, &[]), json!({ "ship_mode": "Sail" }));
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(0, 2)], 0, &[&level]);
+}
+
+#[test]
+fn ship_turns_to_its_beam_and_deploys_the_sail() {
+    let mut game = TestGame::new(&ldtk_level(OPEN_LEVEL, &[]));
+    game.click(0, 2);
+    game.ticks(30);
+
+    let (transform, children) = game
+        .app
+        .world
+        .query_filtered::<(&Transform, &Children), With<Player>>()
+        .single(&game.app.world);
+    let facing = transform.rotation.to_euler(EulerRot::XYZ).2;
+    assert!((facing + std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+    let sail = game.app.world.get::<Sprite>(children[0]).unwrap();
+    assert_eq!(sail.color.a(), 1.);
+}
+
+#[test]
+fn black_holes_swallow_the_ship_and_restart_the_level() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S.O.#.<",
+        ">.......<",
+        ">...E...<",
+        "#^^^^^^^#",
+    ];
+    let mut game = TestGame::new(&ldtk_level(rows, &[]));
+    let level_entities = |game: &mut TestGame| {
+        game.app
+            .world
+            .query::<&LevelEntity>()
+            .iter(&game.app.world)
+            .count()
+    };
+    let spawned = level_entities(&mut game);
+    game.click(0, 2);
+    // a tile and a half to where the black hole is the closest tile
+    game.ticks(44);
+    assert!(game.player().x > 3.);
+
+    game.ticks(2);
+    let player = game.player();
+    assert_eq!((player.x, player.y), (2., 2.));
+    assert!(player.direction.is_none());
+    assert_eq!(game.game_state().activations, 0);
+    // the fired beam is gone with the old ship, the level is spawned once again
+    assert!(game.rays().is_empty());
+    assert_eq!(level_entities(&mut game), spawned);
+}
+
+// an asteroid drifting between 4, 1 and 4, 3 every half second
+fn asteroid_level() -> String {
+    entity_level(
+        OPEN_LEVEL,
+        &[(
+            "Asteroid",
+            (4, 1),
+            json!({ "rail": [{ "cx": 4, "cy": 3 }], "rail_step": 0.5 }),
+        )],
+    )
+}
+
+#[test]
+fn drifting_asteroids_cut_beams_while_they_pass() {
+    let mut game = TestGame::new(&asteroid_level());
+    game.click(0, 2);
+    let beam = |game: &mut TestGame| game.ray_at(0, 2).1.dest_x;
+    assert_eq!(beam(&mut game), 5);
+
+    game.ticks(30);
+    assert_eq!(beam(&mut game), 3);
+    // the ship waits in front of the asteroid
+    game.ticks(25);
+    let player = game.player();
+    assert_eq!(player.x, 3.);
+    assert!(player.direction.is_none());
+
+    game.ticks(10);
+    assert_eq!(beam(&mut game), 5);
+    assert!(game.player().x > 3.);
+}
+
+#[test]
+fn grid_ships_fall_into_gravity_wells_in_line() {
+    let level = entity_level(
+        OPEN_LEVEL,
+        &[(
+            "Gravity_well",
+            (2, 4),
+            json!({ "strength": 1., "radius": 3 }),
+        )],
+    );
+    let mut game = TestGame::new(&level);
+    game.ticks(30);
+    assert!(matches!(game.player().direction, Some(Dir::Downwards)));
+
+    game.ticks(60);
+    let player = game.player();
+    assert_eq!((player.x, player.y), (2., 4.));
+    assert!(player.direction.is_none());
+}
+
+#[test]
+fn gravity_wells_bend_the_path_of_sail_ships() {
+    let level = entity_level(
+        OPEN_LEVEL,
+        &[
+            ("Caster_pulse", (0, 2), json!({ "duration": 0.5 })),
+            (
+                "Gravity_well",
+                (5, 4),
+                json!({ "strength": 2., "radius": 4 }),
+            ),
+        ],
+    );
+    let mut game = TestGame::new(&with_level_fields(&level, json!({ "ship_mode": "Sail" })));
+    game.click(0, 2);
+    game.ticks(60);
+    let player = game.player();
+    assert!(player.velocity.x > 0. && player.velocity.y > 0.);
+    assert!(player.y > 2.);
+}
+
+#[test]
+fn headless_verification_agrees_with_hazards() {
+    // the asteroid first blocks the beam, then the well pulls the ship to the goal
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S..#..<",
+        ">.......<",
+        ">...E...<",
+        "#^^^^^^^#",
+    ];
+    let level = entity_level(
+        rows,
+        &[
+            (
+                "Asteroid",
+                (3, 2),
+                json!({ "rail": [{ "cx": 3, "cy": 3 }], "rail_step": 1. }),
+            ),
+            (
+                "Gravity_well",
+                (4, 4),
+                json!({ "strength": 1., "radius": 2 }),
+            ),
+        ],
+    );
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(0, 2)], 0, &[&level]);
+}
+
+#[test]
+fn headless_verification_reports_lost_ships() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S.O...<",
+        ">...E...<",
+        "#^^^^^^^#",
+    ];
+    let level: Level = serde_json::from_str(&ldtk_level(rows, &[])).unwrap();
+    let replay = Replay {
+        activations: vec![Activation {
+            tick: 0,
+            x: 0,
+            y: 2,
+        }],
+        ..Replay::new(TEST_LEVEL_IID)
+    };
+    assert!(matches!(
+        verify(&replay, &[level]),
+        Err(ReplayError::ShipLost(_))
+    ));
+}
+
+#[test]
+fn lint_flags_blocked_rails_idle_wells_and_hazardous_starts() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.S.....<",
+        ">.O...#.<",
+        ">.......<",
+        ">...E...<",
+        "#^^^^^^^#",
+    ];
+    let level = entity_level(
+        rows,
+        &[
+            (
+                "Asteroid",
+                (1, 1),
+                json!({ "rail": [{ "cx": 3, "cy": 1 }], "rail_step": 1. }),
+            ),
+            (
+                "Asteroid",
+                (5, 2),
+                json!({ "rail": [{ "cx": 7, "cy": 2 }], "rail_step": 1. }),
+            ),
+            (
+                "Gravity_well",
+                (1, 3),
+                json!({ "strength": 0., "radius": 2 }),
+            ),
+        ],
+    );
+    let level: Level = serde_json::from_str(&level).unwrap();
+    assert_eq!(
+        lint_level(&level, &[]),
+        vec![
+            Lint::RailBlocked {
+                entity: "Asteroid",
+                x: 2,
+                y: 1
+            },
+            Lint::RailBlocked {
+                entity: "Asteroid",
+                x: 6,
+                y: 2
+            },
+            Lint::IdleWell { x: 1, y: 3 },
+            Lint::StartInHazard { x: 2, y: 1 },
+        ]
+    );
+
+    let level: Level = serde_json::from_str(&ldtk_level(rows, &[])).unwrap();
+    assert!(lint_level(&level, &[]).is_empty());
+}
+
+#[test]
+fn lint_flags_hazard_levels_without_a_solution() {
+    // the only beam carries the ship into the black hole before the goal
+    let level = ldtk_level(&["######", ">S.OE#", "######"], &[]);
+    let level: Level = serde_json::from_str(&level).unwrap();
+    assert_eq!(
+        lint_level(&level, &[]),
+        vec![Lint::Unsolvable { clicks: 3 }]
+    );
+
+    let level = ldtk_level(&["######", ">S.E.#", "###O##"], &[]);
+    let level: Level = serde_json::from_str(&level).unwrap();
+    assert!(lint_level(&level, &[]).is_empty());
+}
+
+#[test]
+fn ship_picks_up_collectibles_it_passes_and_reports_them_on_the_goal() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S..E#.<",
+        ">.......<",
+        "#^^^^^^^#",
+    ];
+    let level = entity_level(
+        rows,
+        &[
+            ("Collectible", (3, 2), json!({ "kind": "Fuel_cell" })),
+            ("Collectible", (7, 3), json!({ "kind": "Star_fragment" })),
+        ],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(0, 2);
+    game.ticks(40);
+    assert_eq!(game.game_state().collected, vec![(3, 2)]);
+    let hidden: Vec<Visibility> = game
+        .app
+        .world
+        .query::<(&Collectible, &Visibility)>()
+        .iter(&game.app.world)
+        .map(|(_, visibility)| *visibility)
+        .collect();
+    assert!(hidden.contains(&Visibility::Hidden) && hidden.contains(&Visibility::Inherited));
+
+    let mut reader = ManualEventReader::<LevelSolved>::default();
+    while !game.game_state().solved.contains(TEST_LEVEL_IID) {
+        assert!(
+            game.game_state().tick < 600,
+            "the ship never reached the goal"
+        );
+        game.update();
+    }
+    let events = game.app.world.resource::<Events<LevelSolved>>();
+    let solved = reader.iter(events).next().unwrap();
+    assert_eq!(solved.collected, vec![(3, 2)]);
+}
+
+#[test]
+fn saves_keep_collectibles_across_runs() {
+    let mut save = SaveData::default();
+    save.record_collected(TEST_LEVEL_IID, &[(3, 2)], 3);
+    save.record_collected(TEST_LEVEL_IID, &[(1, 1), (3, 2)], 3);
+    let progress = save.level(TEST_LEVEL_IID);
+    assert_eq!(progress.collected, vec![(1, 1), (3, 2)]);
+    // all of them have to be found in one run
+    assert!(!progress.collected_all);
+
+    save.record_collected(TEST_LEVEL_IID, &[(1, 1), (3, 2), (5, 5)], 3);
+    assert!(save.level(TEST_LEVEL_IID).collected_all);
+}
+
+// a door at 5, 2 in the ship's way, the caster at 8, 4 shoots at a trigger at
+// 6, 4 targeting `targets`
+const TRIGGER_ROWS: &[&str] = &[
+    "#vvvvvvv#",
+    ">.......<",
+    ">.S..#.E<",
+    ">.......<",
+    ">.......<",
+    "#^^^^^^^#",
+];
+
+fn trigger_level(
+    targets: &[(&str, (i32, i32))],
+    extra: &[(&str, (i32, i32), serde_json::Value)],
+) -> String {
+    let targets: Vec<_> = targets
+        .iter()
+        .map(|(identifier, tile)| json!({ "entityIid": entity_iid(identifier, *tile) }))
+        .collect();
+    let mut entities = vec![
+        (
+            "Trigger",
+            (6, 4),
+            json!({ "targets": targets, "latch": false }),
+        ),
+        ("Door", (5, 2), json!({})),
+    ];
+    entities.extend(extra.iter().cloned());
+    entity_level(TRIGGER_ROWS, &entities)
+}
+
+fn grid_value(game: &TestGame, x: i32, y: i32) -> i32 {
+    game.app.world.resource::<LevelGrid>().get(x, y)
+}
+
+#[test]
+fn beams_on_triggers_open_doors() {
+    let mut game = TestGame::new(&trigger_level(&[("Door", (5, 2))], &[]));
+    game.click(0, 2);
+    game.ticks(90);
+    // the closed door stops the beam and the ship
+    assert_eq!(game.player().x, 4.);
+    assert_eq!(grid_value(&game, 5, 2), 1);
+
+    game.click(8, 4);
+    game.ticks(2);
+    assert_eq!(grid_value(&game, 5, 2), 0);
+    assert_eq!(game.ray_at(0, 2).1.dest_x, 7);
+    game.ticks(10);
+    assert!(game.player().x > 4.);
+}
+
+#[test]
+fn triggers_fire_remote_casters_without_a_click() {
+    let level = trigger_level(
+        &[("Caster_remote", (0, 2))],
+        &[("Caster_remote", (0, 2), json!({ "action": "Fire" }))],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(8, 4);
+    game.ticks(30);
+    assert!(game.player().x > 2.);
+    assert_eq!(game.game_state().activations, 1);
+}
+
+#[test]
+fn triggers_switch_beams_of_remote_casters_off() {
+    let level = trigger_level(
+        &[("Caster_remote", (0, 2))],
+        &[("Caster_remote", (0, 2), json!({ "action": "Switch_off" }))],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(0, 2);
+    game.ticks(20);
+    game.click(8, 4);
+    game.ticks(60);
+    let player = game.player();
+    assert!(player.x > 2. && player.x < 4.);
+    assert!(player.direction.is_none());
+    assert!(game.rays().iter().any(|(ray, _)| ray.switched_off));
+}
+
+#[test]
+fn headless_verification_agrees_with_triggers() {
+    // one click opens the door and fires the caster behind the ship
+    let level = trigger_level(
+        &[("Door", (5, 2)), ("Caster_remote", (0, 2))],
+        &[("Caster_remote", (0, 2), json!({ "action": "Fire" }))],
+    );
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(8, 4)], 0, &[&level]);
+}
+
+#[test]
+fn lint_flags_misplaced_trigger_entities() {
+    let level = entity_level(
+        TRIGGER_ROWS,
+        &[
+            ("Trigger", (5, 2), json!({ "targets": [] })),
+            ("Door", (3, 3), json!({})),
+            ("Caster_remote", (1, 1), json!({ "action": "Fire" })),
+        ],
+    );
+    let level: Level = serde_json::from_str(&level).unwrap();
+    assert_eq!(
+        lint_level(&level, &[]),
+        vec![
+            Lint::Misplaced {
+                entity: "Trigger",
+                x: 5,
+                y: 2
+            },
+            Lint::IdleTrigger { x: 5, y: 2 },
+            Lint::Misplaced {
+                entity: "Door",
+                x: 3,
+                y: 3
+            },
+            Lint::Misplaced {
+                entity: "Caster_remote",
+                x: 1,
+                y: 1
+            },
+        ]
+    );
+}
+
+// two ships, one in row 2 passing the End tile at 6, 2 and one in row 4
+// passing the End tile at 4, 4
+const FLEET_ROWS: &[&str] = &[
+    "#vvvvvvv#",
+    ">.......<",
+    ">.S...E.<",
+    ">.......<",
+    ">.S.E...<",
+    "#^^^^^^^#",
+];
+
+#[test]
+fn levels_are_solved_once_every_ship_reached_a_goal() {
+    let mut game = TestGame::new(&ldtk_level(FLEET_ROWS, &[]));
+    assert_eq!(game.ships().len(), 2);
+    game.click(0, 2);
+    game.ticks(150);
+    assert_eq!(game.ships()[0].x, 7.);
+    assert_eq!(game.game_state().arrived, vec![0]);
+    assert!(!game.game_state().solved.contains(TEST_LEVEL_IID));
+
+    game.click(0, 4);
+    game.ticks(60);
+    assert!(game.game_state().solved.contains(TEST_LEVEL_IID));
+}
+
+#[test]
+fn ships_with_a_goal_ignore_other_end_tiles() {
+    let level = entity_level(
+        FLEET_ROWS,
+        &[("Ship", (2, 4), json!({ "goal": { "cx": 6, "cy": 2 } }))],
+    );
+    let mut game = TestGame::new(&level);
+    game.click(0, 2);
+    game.click(0, 4);
+    game.ticks(200);
+    assert_eq!(game.ships()[1].x, 7.);
+    assert_eq!(game.game_state().arrived, vec![0]);
+    assert!(!game.game_state().solved.contains(TEST_LEVEL_IID));
+}
+
+#[test]
+fn ships_wait_for_each_other() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S..S..<",
+        ">.......<",
+        "#^^^^^^^#",
+    ];
+    let mut game = TestGame::new(&ldtk_level(rows, &[]));
+    game.click(0, 2);
+    game.ticks(200);
+    let ships = game.ships();
+    // the ship behind stops a tile short of the one in front
+    assert_eq!((ships[0].x, ships[1].x), (6., 7.));
+
+    // beams from both sides push them against each other
+    game.click(8, 2);
+    game.ticks(200);
+    let ships = game.ships();
+    assert!(ships[1].x - ships[0].x >= 1.);
+}
+
+#[test]
+fn headless_verification_agrees_with_fleets() {
+    let level = ldtk_level(FLEET_ROWS, &[]);
+    let mut game = TestGame::new(&level);
+    game.assert_replay_matches(&[(0, 2), (0, 4)], 20, &[&level]);
+}
+
+#[test]
+fn shipped_replays_reach_their_goal() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let levels = read_project_levels(&assets.join(PROJECT_PATH)).unwrap();
+    for entry in fs::read_dir(assets.join("replays")).unwrap() {
+        let path = entry.unwrap().path();
+        let replay = read_replay(&path).unwrap();
+        if let Err(error) = verify(&replay, &levels) {
+            panic!("{}: {}", path.display(), error);
+        }
+    }
+}
+
+// the ship goes right along row 2 and down column 7 to the End tile
+const TURN_ROWS: &[&str] = &[
+    "#####v#v#",
+    "#.......#",
+    ">.S.....#",
+    "#.......#",
+    "#......E#",
+    "#########",
+];
+
+#[test]
+fn solver_counts_every_shortest_solution() {
+    let level: Level = serde_json::from_str(&ldtk_level(TURN_ROWS, &[])).unwrap();
+    let levels = std::slice::from_ref(&level);
+    assert!(solve(&level, levels, 1).is_none());
+
+    // firing down column 7 first works as well, the ship turns into it
+    let solution = solve(&level, levels, 3).unwrap();
+    assert_eq!(solution.clicks(), 2);
+    assert_eq!(solution.count, 2);
+
+    let mut replay = Replay::new(TEST_LEVEL_IID);
+    replay.activations = solution.activations.clone();
+    assert_eq!(verify(&replay, levels).unwrap(), solution.ticks);
+}
+
+#[test]
+fn levels_without_par_get_it_from_the_solver() {
+    let level = ldtk_level(TURN_ROWS, &[]);
+    let mut game = TestGame::new(&level);
+    game.app
+        .world
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::LevelSelect);
+    let start = Instant::now();
+    let computed = |game: &TestGame| game.app.world.resource::<ComputedPars>().0.clone();
+    while !computed(&game).contains_key(TEST_LEVEL_IID) {
+        assert!(
+            start.elapsed() < Duration::from_secs(60),
+            "the solver never finished"
+        );
+        game.update();
+    }
+
+    let level: Level = serde_json::from_str(&level).unwrap();
+    let solution = solve(&level, std::slice::from_ref(&level), 3).unwrap();
+    assert_eq!(computed(&game)[TEST_LEVEL_IID], solution.par());
+}
+
+#[test]
+fn solver_finds_a_unique_solution() {
+    let rows = &[
+        "#vvvvvvv#",
+        ">.......<",
+        ">.S...E.<",
+        ">.......<",
+        "#^^^^^^^#",
+    ];
+    let level: Level = serde_json::from_str(&ldtk_level(rows, &[])).unwrap();
+    let solution = solve(&level, std::slice::from_ref(&level), 2).unwrap();
+    assert_eq!(solution.clicks(), 1);
+    assert_eq!(solution.count, 1);
+    assert_eq!(
+        solution.activations,
+        vec![Activation {
+            tick: 1,
+            x: 0,
+            y: 2
+        }]
+    );
+}
+
+fn small_config() -> GeneratorConfig {
+    GeneratorConfig {
+        width: 8,
+        height: 8,
+        obstacles: 5,
+        casters: 1,
+        border_casters: 4,
+        clicks: 2,
+        attempts: 500,
+    }
+}
+
+#[test]
+fn generated_levels_have_the_wanted_unique_solution() {
+    let template = serde_json::from_str(&with_level_fields(
+        &ldtk_level(TURN_ROWS, &[]),
+        json!({ "par_rays": null, "par_time": null }),
+    ))
+    .unwrap();
+    let config = small_config();
+    let generate = || generate(&config, 3, &template, "Generated_3", 100, (0, 0)).unwrap();
+    let generated = generate();
+    assert_eq!(generated.level, generate().level);
+
+    let level: Level = serde_json::from_value(generated.level).unwrap();
+    let levels = std::slice::from_ref(&level);
+    let solution = solve(&level, levels, config.clicks).unwrap();
+    assert_eq!((solution.clicks(), solution.count), (2, 1));
+    assert_eq!(solution, generated.solution);
+    assert_eq!(Par::from_level(&level), solution.par());
+    assert_eq!(level.find_tiles(6).len(), 1);
+    assert_eq!(level.find_tiles(7).len(), 1);
+}
+
+#[test]
+fn generated_levels_are_added_to_the_project() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let project_dir = std::env::temp_dir().join(format!("sole_generate_{}", std::process::id()));
+    fs::create_dir_all(project_dir.join("level")).unwrap();
+    for entry in fs::read_dir(assets.join("level")).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(
+            &path,
+            project_dir.join("level").join(path.file_name().unwrap()),
+        )
+        .unwrap();
+    }
+    let project = project_dir.join(PROJECT_PATH);
+    fs::copy(assets.join(PROJECT_PATH), &project).unwrap();
+    let before = read_project_levels(&project).unwrap();
+
+    let (path, generated) = add_generated_level(&project, &small_config(), 3).unwrap();
+    assert_eq!(path, project_dir.join("level").join("Generated_3.ldtkl"));
+    let levels = read_project_levels(&project).unwrap();
+    assert_eq!(levels.len(), before.len() + 1);
+    let level = levels.last().unwrap();
+    assert_eq!(level.identifier, "Generated_3");
+    assert!(lint_level(level, &levels).is_empty());
+    // right of every other level
+    let right = |level: &Level| level.tile_rect().x + level.tile_rect().width;
+    assert!(before
+        .iter()
+        .all(|other| right(other) < level.tile_rect().x));
+
+    let mut replay = Replay::new(&level.iid);
+    replay.activations = generated.solution.activations.clone();
+    assert_eq!(verify(&replay, &levels).unwrap(), generated.solution.ticks);
+    assert!(add_generated_level(&project, &small_config(), 3).is_err());
+    fs::remove_dir_all(project_dir).unwrap();
+}
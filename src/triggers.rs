@@ -0,0 +1,450 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    caster::{move_casters, Beam, MovingCaster},
+    fire_caster,
+    level::Level,
+    loading::GameAssets,
+    move_player,
+    priority::RayPriority,
+    pulse::BeamTiming,
+    world::LevelEntity,
+    GameState, GameSystemSets, LevelGrid, Ray, RayCaster, SimulationSets, TILE_SIZE,
+};
+
+// not authored, written into the LevelGrid where a Trigger is
+pub const TRIGGER_TILE: i32 = 11;
+// the IntGrid value of a closed door, an obstacle
+const DOOR_CLOSED: i32 = 1;
+
+const TRIGGER_COLOR: Color = Color::rgb(0.2, 0.45, 0.45);
+const TRIGGER_ACTIVE_COLOR: Color = Color::rgb(0.3, 1., 0.9);
+
+// Triggers
+//
+// Trigger entities stop light like obstacles and are active while a lit ray
+// ends on their tile, or for good once hit with `latch`. Their `targets` field
+// references entities in the same or a neighbouring level:
+// - Door: put on an Obstacle tile, the tile is empty while any active trigger
+//   targets it
+// - Caster_remote: put on a caster, its `action` decides what triggers do to
+//   it. Fire fires the caster once whenever a trigger turns active, it does
+//   not count as a click. Switch_off keeps every beam of the caster dark while
+//   a trigger is active.
+// Like everything else triggers start over whenever a level is entered.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Door { x: i32, y: i32 },
+    // casters by the world tile they start on
+    Fire { x: i32, y: i32 },
+    SwitchOff { x: i32, y: i32 },
+}
+
+// sent for every target of a trigger that turned active or inactive
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetEvent {
+    pub target: Target,
+    pub active: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Trigger {
+    x: i32,
+    y: i32,
+    targets: Vec<Target>,
+    latch: bool,
+    active: bool,
+}
+
+// Components
+
+#[derive(Component)]
+struct TriggerTile {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Component)]
+struct DoorTile {
+    x: i32,
+    y: i32,
+}
+
+// Resources
+
+// the triggers of the streamed in levels, in world tile coordinates
+#[derive(Resource, Default, Clone)]
+pub struct TriggerNetwork {
+    triggers: Vec<Trigger>,
+}
+
+impl TriggerNetwork {
+    // targets in levels that are not streamed in are left out
+    pub fn new<'a>(levels: impl IntoIterator<Item = &'a Level>) -> Self {
+        let levels: Vec<&Level> = levels.into_iter().collect();
+        let targets: HashMap<&str, Target> = levels
+            .iter()
+            .flat_map(|level| level_targets(level))
+            .collect();
+        let triggers = levels
+            .iter()
+            .flat_map(|level| {
+                let rect = level.tile_rect();
+                level.entities("Trigger").map(move |entity| (rect, entity))
+            })
+            .map(|(rect, entity)| Trigger {
+                x: rect.x + entity.grid[0],
+                y: rect.y + entity.grid[1],
+                targets: target_iids(entity.field("targets"))
+                    .filter_map(|iid| targets.get(iid).cloned())
+                    .collect(),
+                latch: entity
+                    .field("latch")
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or_default(),
+                active: false,
+            })
+            .collect();
+        TriggerNetwork { triggers }
+    }
+
+    // `hits` are the tiles lit rays end on this tick, returns what changed
+    pub fn update(&mut self, hits: &HashSet<(i32, i32)>) -> Vec<TargetEvent> {
+        let mut changes = Vec::new();
+        for trigger in &mut self.triggers {
+            let active =
+                hits.contains(&(trigger.x, trigger.y)) || (trigger.latch && trigger.active);
+            if active == trigger.active {
+                continue;
+            }
+            trigger.active = active;
+            changes.extend(trigger.targets.iter().map(|target| TargetEvent {
+                target: target.clone(),
+                active,
+            }));
+        }
+        changes
+    }
+
+    pub fn is_active(&self, x: i32, y: i32) -> bool {
+        self.triggers
+            .iter()
+            .any(|trigger| trigger.active && (trigger.x, trigger.y) == (x, y))
+    }
+
+    fn targeted(&self, target: &Target) -> bool {
+        self.triggers
+            .iter()
+            .any(|trigger| trigger.active && trigger.targets.contains(target))
+    }
+
+    // beams of the caster starting on x, y are dark
+    pub fn switched_off(&self, (x, y): (i32, i32)) -> bool {
+        self.targeted(&Target::SwitchOff { x, y })
+    }
+
+    // opens and closes doors in the grid, only touching tiles that change
+    pub fn place_doors(&self, grid: &mut LevelGrid) {
+        let doors = self.triggers.iter().flat_map(|trigger| &trigger.targets);
+        for target in doors {
+            let Target::Door { x, y } = *target else {
+                continue;
+            };
+            let value = if self.targeted(target) {
+                0
+            } else {
+                DOOR_CLOSED
+            };
+            if grid.get(x, y) != value {
+                grid.set(x, y, value);
+            }
+        }
+    }
+}
+
+// Plugin
+
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TargetEvent>();
+        // beams are traced for this tick before they hit anything
+        app.add_systems(
+            (update_triggers, fire_remote_casters)
+                .chain()
+                .after(move_casters)
+                .before(move_player)
+                .in_set(SimulationSets::Step)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(show_triggers.in_set(GameSystemSets::Logic));
+    }
+}
+
+// EntityRef values of a `targets` field
+fn target_iids(value: Option<&serde_json::Value>) -> impl Iterator<Item = &str> {
+    value
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|reference| reference.get("entityIid")?.as_str())
+}
+
+// what the Door and Caster_remote entities of a level are, keyed by entity iid
+fn level_targets(level: &Level) -> Vec<(&str, Target)> {
+    let rect = level.tile_rect();
+    let doors = level.entities("Door").map(|entity| {
+        let (x, y) = (rect.x + entity.grid[0], rect.y + entity.grid[1]);
+        (entity.iid.as_str(), Target::Door { x, y })
+    });
+    let remotes = level.entities("Caster_remote").map(|entity| {
+        let (x, y) = (rect.x + entity.grid[0], rect.y + entity.grid[1]);
+        let target = match entity.field("action").and_then(|value| value.as_str()) {
+            Some("Switch_off") => Target::SwitchOff { x, y },
+            _ => Target::Fire { x, y },
+        };
+        (entity.iid.as_str(), target)
+    });
+    doors.chain(remotes).collect()
+}
+
+// world tiles of the Trigger entities of a level
+pub fn trigger_tiles(level: &Level) -> Vec<(i32, i32)> {
+    let rect = level.tile_rect();
+    level
+        .entities("Trigger")
+        .map(|entity| (rect.x + entity.grid[0], rect.y + entity.grid[1]))
+        .collect()
+}
+
+// world tiles of the Door entities of a level
+pub fn door_tiles(level: &Level) -> HashSet<(i32, i32)> {
+    let rect = level.tile_rect();
+    level
+        .entities("Door")
+        .map(|entity| (rect.x + entity.grid[0], rect.y + entity.grid[1]))
+        .collect()
+}
+
+// the tile past the head of a ray, where its light stops. Beams point the way
+// of their caster, a ray only a tile long cannot tell by itself.
+pub fn end_tile(ray: &Ray, caster: Option<&RayCaster>) -> (i32, i32) {
+    let dir = caster.map_or_else(|| ray.dir(), |caster| caster.dir.clone());
+    let (step_x, step_y) = dir.step();
+    let (x, y) = if step_x + step_y > 0 {
+        (ray.dest_x, ray.dest_y)
+    } else {
+        (ray.src_x, ray.src_y)
+    };
+    (x + step_x, y + step_y)
+}
+
+// the tiles lit rays end on during `tick`, each ray with the caster of its
+// beam or None for authored rays
+pub fn trigger_hits<'a>(
+    rays: impl IntoIterator<Item = (&'a Ray, Option<&'a RayCaster>)>,
+    tick: u32,
+) -> HashSet<(i32, i32)> {
+    rays.into_iter()
+        .filter(|(ray, _)| ray.lit(tick))
+        .map(|(ray, caster)| end_tile(ray, caster))
+        .collect()
+}
+
+// the home tiles of the casters that `changes` on `tick` fire, with the tick
+// their beams are born on. Remote beams light up on the next tick.
+pub fn remote_fires<'a>(
+    changes: impl IntoIterator<Item = &'a TargetEvent>,
+    tick: u32,
+) -> Vec<((i32, i32), u32)> {
+    changes
+        .into_iter()
+        .filter_map(|change| match *change {
+            TargetEvent {
+                target: Target::Fire { x, y },
+                active: true,
+            } => Some(((x, y), tick + 1)),
+            _ => None,
+        })
+        .collect()
+}
+
+// the tile a caster starts on, which Caster_remote entities are placed on
+pub fn home_tile(ray_caster: &RayCaster, moving: Option<&MovingCaster>) -> (i32, i32) {
+    moving.map_or((ray_caster.pos_x, ray_caster.pos_y), |moving| {
+        moving.start_tile()
+    })
+}
+
+pub fn spawn_triggers(
+    level: &Level,
+    game_assets: &GameAssets,
+    level_entity: &LevelEntity,
+    commands: &mut Commands,
+) {
+    let sprite = |(x, y): (i32, i32), color: Color| SpriteBundle {
+        texture: game_assets.tile.clone(),
+        sprite: Sprite {
+            color,
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(x as f32 * TILE_SIZE, -y as f32 * TILE_SIZE, 100.),
+        ..Default::default()
+    };
+    for (x, y) in trigger_tiles(level) {
+        commands.spawn((
+            sprite((x, y), TRIGGER_COLOR),
+            TriggerTile { x, y },
+            level_entity.clone(),
+        ));
+    }
+    for (x, y) in door_tiles(level) {
+        commands.spawn((
+            sprite((x, y), Color::rgb(0.55, 0.6, 0.7)),
+            DoorTile { x, y },
+            level_entity.clone(),
+        ));
+    }
+}
+
+// finds the tiles lit rays end on, updates the triggers and switches beams
+// off, doors open for the ship on this tick and for beams on the next
+#[allow(clippy::type_complexity)]
+fn update_triggers(
+    game_state: Res<GameState>,
+    mut network: ResMut<TriggerNetwork>,
+    mut grid: ResMut<LevelGrid>,
+    mut changes: EventWriter<TargetEvent>,
+    q_caster: Query<(&RayCaster, Option<&MovingCaster>)>,
+    mut q_ray: Query<(&mut Ray, Option<&Beam>)>,
+) {
+    let caster = |beam: Option<&Beam>| beam.and_then(|beam| q_caster.get(beam.caster).ok());
+    let rays = q_ray
+        .iter()
+        .map(|(ray, beam)| (ray, caster(beam).map(|(ray_caster, _)| ray_caster)));
+    let hits = trigger_hits(rays, game_state.tick);
+    let changed = network.update(&hits);
+    if !changed.is_empty() {
+        network.place_doors(&mut grid);
+    }
+    for (mut ray, beam) in &mut q_ray {
+        let Some((ray_caster, moving)) = caster(beam) else {
+            continue;
+        };
+        let off = network.switched_off(home_tile(ray_caster, moving));
+        if ray.switched_off != off {
+            ray.switched_off = off;
+        }
+    }
+    changes.send_batch(changed);
+}
+
+// Fire targets fire their caster once, see remote_fires
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn fire_remote_casters(
+    mut changes: EventReader<TargetEvent>,
+    grid: Res<LevelGrid>,
+    priority: Res<RayPriority>,
+    game_assets: Res<GameAssets>,
+    mut game_state: ResMut<GameState>,
+    mut commands: Commands,
+    q_caster: Query<(
+        Entity,
+        &RayCaster,
+        Option<&MovingCaster>,
+        Option<&BeamTiming>,
+        &LevelEntity,
+    )>,
+) {
+    let tick = game_state.tick;
+    for (home, born) in remote_fires(changes.iter(), tick) {
+        let caster = q_caster
+            .iter()
+            .find(|(_, ray_caster, moving, _, _)| home_tile(ray_caster, *moving) == home);
+        let Some((entity, ray_caster, _, timing, level_entity)) = caster else {
+            continue;
+        };
+        fire_caster(
+            ray_caster,
+            entity,
+            timing,
+            level_entity,
+            &grid,
+            &priority,
+            &game_assets,
+            &mut game_state,
+            born,
+            &mut commands,
+        );
+    }
+}
+
+// lights up active triggers and hides open doors
+fn show_triggers(
+    network: Res<TriggerNetwork>,
+    grid: Res<LevelGrid>,
+    mut q_trigger: Query<(&TriggerTile, &mut Sprite)>,
+    mut q_door: Query<(&DoorTile, &mut Visibility)>,
+) {
+    for (trigger, mut sprite) in &mut q_trigger {
+        sprite.color = if network.is_active(trigger.x, trigger.y) {
+            TRIGGER_ACTIVE_COLOR
+        } else {
+            TRIGGER_COLOR
+        };
+    }
+    for (door, mut visibility) in &mut q_door {
+        *visibility = if grid.get(door.x, door.y) == DOOR_CLOSED {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(latch: bool) -> TriggerNetwork {
+        TriggerNetwork {
+            triggers: vec![Trigger {
+                x: 1,
+                y: 1,
+                targets: vec![
+                    Target::Door { x: 3, y: 3 },
+                    Target::SwitchOff { x: 0, y: 5 },
+                ],
+                latch,
+                active: false,
+            }],
+        }
+    }
+
+    #[test]
+    fn triggers_report_their_targets_on_edges_only() {
+        let mut network = network(false);
+        let hit = HashSet::from([(1, 1)]);
+        let changes = network.update(&hit);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|change| change.active));
+        assert!(network.update(&hit).is_empty());
+        assert!(network.switched_off((0, 5)));
+
+        let changes = network.update(&HashSet::new());
+        assert!(changes.iter().all(|change| !change.active));
+        assert!(!network.switched_off((0, 5)));
+    }
+
+    #[test]
+    fn latched_triggers_stay_active() {
+        let mut network = network(true);
+        network.update(&HashSet::from([(1, 1)]));
+        assert!(network.update(&HashSet::new()).is_empty());
+        assert!(network.is_active(1, 1));
+    }
+}
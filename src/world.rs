@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    fleet::{spawn_fleet, FleetMember},
+    level::{LdtkProject, Level, LevelRect},
+    loading::GameAssets,
+    physics::ShipMode,
+    priority::RayPriority,
+    spawn_level,
+    triggers::TriggerNetwork,
+    AppState, GameState, GameSystemSets, LevelGrid, Player, Ray, ShipLost,
+};
+
+// played when no level was picked in the level select
+const START_LEVEL: &str = "Level_3";
+// where the ship starts in levels without a Start tile, level local
+const DEFAULT_START: (i32, i32) = (3, 10);
+pub const START_TILE: i32 = 6;
+
+// Components
+
+// everything spawned for a level, despawned when the level is streamed out
+#[derive(Component, Clone)]
+pub struct LevelEntity(pub String);
+
+// Resources
+
+// the level the ship is in, its neighbours are streamed in around it
+#[derive(Resource, Default)]
+pub struct CurrentLevel {
+    pub iid: String,
+    pub rect: LevelRect,
+    pub ship_mode: ShipMode,
+}
+
+#[derive(Resource, Default)]
+struct StreamedLevels(HashSet<String>);
+
+// iid of the level to start in when entering AppState::InGame
+#[derive(Resource, Default)]
+pub struct SelectedLevel(pub Option<String>);
+
+// all levels of the loaded LDtk project
+#[derive(SystemParam)]
+pub struct WorldLevels<'w> {
+    game_assets: Res<'w, GameAssets>,
+    projects: Res<'w, Assets<LdtkProject>>,
+    levels: Res<'w, Assets<Level>>,
+}
+
+impl<'w> WorldLevels<'w> {
+    pub fn iter(&self) -> impl Iterator<Item = &Level> {
+        self.projects
+            .get(&self.game_assets.project)
+            .into_iter()
+            .flat_map(|project| project.levels.iter())
+            .filter_map(|handle| self.levels.get(handle))
+    }
+
+    pub fn by_iid(&self, iid: &str) -> Option<&Level> {
+        self.iter().find(|level| level.iid == iid)
+    }
+
+    pub fn by_identifier(&self, identifier: &str) -> Option<&Level> {
+        self.iter().find(|level| level.identifier == identifier)
+    }
+
+    // the level covering a world tile, levels never overlap in LDtk
+    pub fn at_tile(&self, x: i32, y: i32) -> Option<&Level> {
+        self.iter().find(|level| level.tile_rect().contains(x, y))
+    }
+}
+
+// Plugin
+
+pub struct WorldStreamingPlugin;
+
+impl Plugin for WorldStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CurrentLevel::default());
+        app.insert_resource(StreamedLevels::default());
+        app.insert_resource(SelectedLevel::default());
+        app.add_system(start_world.in_schedule(OnEnter(AppState::InGame)));
+        app.add_system(leave_world.in_schedule(OnExit(AppState::InGame)));
+        app.add_systems((follow_ship, restart_level).in_set(GameSystemSets::Logic));
+    }
+}
+
+// the state streaming touches, bundled so systems can switch levels
+#[derive(SystemParam)]
+struct LevelStreamer<'w, 's> {
+    commands: Commands<'w, 's>,
+    current: ResMut<'w, CurrentLevel>,
+    streamed: ResMut<'w, StreamedLevels>,
+    grid: ResMut<'w, LevelGrid>,
+    priority: ResMut<'w, RayPriority>,
+    network: ResMut<'w, TriggerNetwork>,
+    game_state: ResMut<'w, GameState>,
+    q_level_entities: Query<'w, 's, (Entity, &'static LevelEntity)>,
+    q_rays: Query<'w, 's, &'static mut Ray>,
+    q_players: Query<'w, 's, (&'static mut Player, &'static FleetMember)>,
+}
+
+impl<'w, 's> LevelStreamer<'w, 's> {
+    // makes `level` current and keeps exactly it and its LDtk neighbours spawned
+    fn enter(&mut self, level: &Level, world_levels: &WorldLevels) {
+        self.current.iid = level.iid.clone();
+        self.current.rect = level.tile_rect();
+        let ship_mode = ShipMode::from_level(level);
+        // grid ships only turn on tile centres, ships sailing in land on the
+        // centre of their tile
+        if self.current.ship_mode == ShipMode::Sail && ship_mode == ShipMode::Grid {
+            for (mut player, _) in &mut self.q_players {
+                let (x, y) = player.tile();
+                player.x = x as f32;
+                player.y = y as f32;
+                player.velocity = Vec2::ZERO;
+            }
+        }
+        self.current.ship_mode = ship_mode;
+        // rays of the levels that stay spawned go on as if nothing happened
+        for mut ray in &mut self.q_rays {
+            ray.rebase(self.game_state.tick);
+        }
+        self.game_state.activations = 0;
+        self.game_state.tick = 0;
+        self.game_state.collected.clear();
+        self.game_state.arrived.clear();
+
+        let wanted: HashSet<String> = std::iter::once(level.iid.clone())
+            .chain(level.neighbours.iter().map(|n| n.level_iid.clone()))
+            .collect();
+
+        // keeps the entities of levels that stay streamed, none once restart_level
+        // cleared StreamedLevels
+        self.streamed.0.retain(|iid| wanted.contains(iid));
+        for (entity, level_entity) in &self.q_level_entities {
+            if !self.streamed.0.contains(&level_entity.0) {
+                self.commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let wanted_levels: Vec<&Level> = wanted
+            .iter()
+            .filter_map(|iid| world_levels.by_iid(iid))
+            .collect();
+        *self.priority = RayPriority::new(wanted_levels.iter().copied());
+        for level in &wanted_levels {
+            if self.streamed.0.insert(level.iid.clone()) {
+                spawn_level(
+                    level,
+                    &world_levels.game_assets,
+                    &self.priority,
+                    &mut self.game_state,
+                    &mut self.commands,
+                );
+            }
+        }
+        *self.network = TriggerNetwork::new(wanted_levels.iter().copied());
+        *self.grid = LevelGrid::new(wanted_levels);
+    }
+}
+
+// where the ships spawn in a level row by row, in world tile coordinates
+pub fn start_positions(level: &Level) -> Vec<(f32, f32)> {
+    let rect = level.tile_rect();
+    let mut starts = level.find_tiles(START_TILE);
+    if starts.is_empty() {
+        starts.push(DEFAULT_START);
+    }
+    starts
+        .into_iter()
+        .map(|(x, y)| ((rect.x + x) as f32, (rect.y + y) as f32))
+        .collect()
+}
+
+fn start_world(
+    world_levels: WorldLevels,
+    selected: Res<SelectedLevel>,
+    mut streamer: LevelStreamer,
+) {
+    let level = match &selected.0 {
+        Some(iid) => world_levels.by_iid(iid),
+        None => world_levels.by_identifier(START_LEVEL),
+    };
+    let Some(level) = level else {
+        error!("the start level is missing from the LDtk project");
+        return;
+    };
+
+    spawn_fleet(level, &world_levels.game_assets, &mut streamer.commands);
+    streamer.enter(level, &world_levels);
+}
+
+// switches the current level once the lead ship of the fleet crossed into another one
+fn follow_ship(world_levels: WorldLevels, mut streamer: LevelStreamer) {
+    let lead = streamer
+        .q_players
+        .iter()
+        .min_by_key(|(_, member)| member.index)
+        .map(|(player, _)| player.tile());
+    let Some((x, y)) = lead else {
+        return;
+    };
+    // between levels the ship stays in the one it left
+    let Some(level) = world_levels.at_tile(x, y) else {
+        return;
+    };
+    if level.iid != streamer.current.iid {
+        streamer.enter(level, &world_levels);
+    }
+}
+
+// puts the fleet of a lost ship back on the start of its level and spawns the
+// level and its neighbours anew, so rays and hazards start over with it
+fn restart_level(
+    mut lost: EventReader<ShipLost>,
+    world_levels: WorldLevels,
+    mut streamer: LevelStreamer,
+    q_player: Query<Entity, With<Player>>,
+) {
+    if lost.iter().count() == 0 {
+        return;
+    }
+    let Some(level) = world_levels.by_iid(&streamer.current.iid) else {
+        return;
+    };
+    for entity in &q_player {
+        streamer.commands.entity(entity).despawn_recursive();
+    }
+    streamer.streamed.0.clear();
+
+    spawn_fleet(level, &world_levels.game_assets, &mut streamer.commands);
+    streamer.enter(level, &world_levels);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn leave_world(
+    mut commands: Commands,
+    mut current: ResMut<CurrentLevel>,
+    mut streamed: ResMut<StreamedLevels>,
+    mut grid: ResMut<LevelGrid>,
+    mut priority: ResMut<RayPriority>,
+    mut network: ResMut<TriggerNetwork>,
+    mut game_state: ResMut<GameState>,
+    q_despawn: Query<Entity, Or<(With<LevelEntity>, With<Player>)>>,
+) {
+    for entity in &q_despawn {
+        commands.entity(entity).despawn_recursive();
+    }
+    *current = CurrentLevel::default();
+    *streamed = StreamedLevels::default();
+    *grid = LevelGrid::default();
+    *priority = RayPriority::default();
+    *network = TriggerNetwork::default();
+    *game_state = GameState::default();
+}